@@ -0,0 +1,120 @@
+use crate::states_manager::{self, AppState};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Value of the `format` field, tells a backup archive from other JSON files
+pub const ARCHIVE_FORMAT: &str = "overtls-gui-backup";
+
+/// Version of the archive envelope, the state inside carries its own `schema_version`
+pub const ARCHIVE_FORMAT_VERSION: u32 = 1;
+
+/// A single file holding the whole application state: every profile with its nodes, the settings and the window state.
+///
+/// `payload` is the state exactly as it would be written to `config.json`, so the nodes stay encrypted
+/// when a master passphrase is set, and old archives go through the same migrations as old config files.
+#[derive(Serialize, Deserialize)]
+pub struct BackupArchive {
+    pub format: String,
+    pub format_version: u32,
+    pub app_version: String,
+    pub created: String,
+    /// Hex SHA-256 of `payload`
+    pub sha256: String,
+    pub payload: String,
+}
+
+pub fn create_archive(state: &AppState) -> std::io::Result<String> {
+    let payload = states_manager::serialize_app_state(state)?;
+    let archive = BackupArchive {
+        format: ARCHIVE_FORMAT.to_string(),
+        format_version: ARCHIVE_FORMAT_VERSION,
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+        created: chrono::Local::now().to_rfc3339(),
+        sha256: sha256_hex(&payload),
+        payload,
+    };
+    serde_json::to_string_pretty(&archive).map_err(|e| std::io::Error::other(format!("Failed to serialize backup: {e}")))
+}
+
+/// Check the envelope and the checksum, then parse the state. Its nodes may still be locked by a master passphrase.
+pub fn read_archive(contents: &str) -> std::io::Result<AppState> {
+    let invalid_data = |msg: String| std::io::Error::new(std::io::ErrorKind::InvalidData, msg);
+    let archive: BackupArchive = serde_json::from_str(contents).map_err(|e| invalid_data(format!("Not a backup file: {e}")))?;
+    if archive.format != ARCHIVE_FORMAT {
+        return Err(invalid_data(format!("Unknown backup format '{}'", archive.format)));
+    }
+    if archive.format_version > ARCHIVE_FORMAT_VERSION {
+        return Err(invalid_data(format!(
+            "Backup format version {} is newer than the supported version {ARCHIVE_FORMAT_VERSION}, please upgrade the app",
+            archive.format_version
+        )));
+    }
+    if !sha256_hex(&archive.payload).eq_ignore_ascii_case(&archive.sha256) {
+        return Err(invalid_data("Backup file is corrupted, checksum mismatch".to_string()));
+    }
+    states_manager::parse_app_state(&archive.payload)
+}
+
+fn sha256_hex(data: &str) -> String {
+    format!("{:x}", Sha256::digest(data.as_bytes()))
+}
+
+/// Human-readable summary of what restoring `archive` over `current` would do
+pub fn restore_preview(current: &AppState, archive: &AppState) -> String {
+    let describe = |state: &AppState| {
+        let mut profiles = vec![format!("{} ({} nodes)", state.active_profile, state.remote_nodes.len())];
+        profiles.extend(
+            state
+                .profiles
+                .iter()
+                .map(|p| format!("{} ({} nodes)", p.name, p.remote_nodes.len())),
+        );
+        profiles.join(", ")
+    };
+    let added = current.clone().merge_nodes_from(archive.clone());
+    format!(
+        "Backup: {}\nCurrent: {}\n\n\
+         Replace: the current profiles, nodes and settings are replaced by the backup.\n\
+         Merge: the current data is kept and {added} node(s) only found in the backup are added.",
+        describe(archive),
+        describe(current),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::states_manager::test_fixtures::fixture_state as sample_state;
+
+    #[test]
+    fn archive_round_trip() {
+        let mut state = sample_state();
+        state.clone_profile(states_manager::DEFAULT_PROFILE_NAME, "Staging").unwrap();
+        let contents = create_archive(&state).unwrap();
+
+        let restored = read_archive(&contents).unwrap();
+        assert_eq!(
+            states_manager::serialize_app_state(&restored).unwrap(),
+            states_manager::serialize_app_state(&state).unwrap()
+        );
+    }
+
+    #[test]
+    fn read_archive_detects_tampering() {
+        let contents = create_archive(&sample_state()).unwrap();
+        let mut archive: BackupArchive = serde_json::from_str(&contents).unwrap();
+        archive.payload = archive.payload.replace("Example", "Tampered");
+        let tampered = serde_json::to_string(&archive).unwrap();
+
+        let err = read_archive(&tampered).err().unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn read_archive_rejects_newer_format() {
+        let contents = create_archive(&sample_state()).unwrap();
+        let mut archive: BackupArchive = serde_json::from_str(&contents).unwrap();
+        archive.format_version = ARCHIVE_FORMAT_VERSION + 1;
+        assert!(read_archive(&serde_json::to_string(&archive).unwrap()).is_err());
+    }
+}
@@ -0,0 +1,185 @@
+use std::{ffi::OsString, path::PathBuf};
+
+/// Environment variable pointing to the config file to use, overridden by `--config`
+pub const CONFIG_ENV_VAR: &str = "OVERTLS_GUI_CONFIG";
+
+/// Marker file next to the executable that enables portable mode
+pub const PORTABLE_MARKER: &str = concat!(env!("CARGO_PKG_NAME"), ".portable");
+
+/// Directory next to the executable holding the whole state in portable mode
+pub const PORTABLE_DATA_DIR: &str = concat!(env!("CARGO_PKG_NAME"), "-data");
+
+pub const USAGE: &str = "Usage: overtls-gui [options] [<link>|<file>...]
+
+Arguments:
+  <link>           ssr:// or overtls:// link of a node to import
+  <file>           Node file to import: JSON config, QR code image or text file of links
+
+Options:
+  --config <file>           Use <file> instead of the default config.json
+  --register-url-handler    Register as the handler of ssr:// and overtls:// links, then exit (Linux only)
+  --new-instance            Run alongside the instance already using the same config directory
+  -h, --help                Show this help
+
+Only one instance runs per config directory: launching the app again brings its window
+to the front, and links and files given then are imported by it.
+
+The config file can also be set with the OVERTLS_GUI_CONFIG environment variable.
+If a file named overtls-gui.portable sits next to the executable, all state is kept
+in the overtls-gui-data directory beside it.";
+
+#[derive(Debug, Default, PartialEq)]
+pub struct CliArgs {
+    pub config: Option<PathBuf>,
+    pub help: bool,
+    pub register_url_handler: bool,
+    pub new_instance: bool,
+    /// Share links and absolute paths of node files to import
+    pub imports: Vec<String>,
+}
+
+impl CliArgs {
+    pub fn parse() -> std::io::Result<Self> {
+        Self::parse_from(std::env::args_os().skip(1))
+    }
+
+    /// Parse the arguments, without the program name
+    pub fn parse_from(args: impl IntoIterator<Item = OsString>) -> std::io::Result<Self> {
+        let invalid_input = |msg: String| std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("{msg}\n\n{USAGE}"));
+        let mut result = CliArgs::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let arg_str = arg.to_string_lossy();
+            if arg_str == "-h" || arg_str == "--help" {
+                result.help = true;
+            } else if arg_str == "--config" {
+                let path = args.next().ok_or_else(|| invalid_input("Missing value for --config".to_string()))?;
+                result.config = Some(PathBuf::from(path));
+            } else if let Some(path) = arg_str.strip_prefix("--config=") {
+                result.config = Some(PathBuf::from(path));
+            } else if arg_str == "--register-url-handler" {
+                result.register_url_handler = true;
+            } else if arg_str == "--new-instance" {
+                result.new_instance = true;
+            } else if arg_str.starts_with('-') {
+                return Err(invalid_input(format!("Unknown argument '{arg_str}'")));
+            } else if crate::share_link::SHARE_LINK_SCHEMES
+                .iter()
+                .any(|scheme| arg_str.starts_with(scheme))
+            {
+                result.imports.push(arg_str.into_owned());
+            } else {
+                // Desktop launchers may pass files as `file://` URLs.
+                // Absolute, the running instance the file is forwarded to has its own working directory.
+                let path = match arg_str.strip_prefix("file://") {
+                    Some(path) => PathBuf::from(path),
+                    None => PathBuf::from(&arg),
+                };
+                let path = std::path::absolute(path)?;
+                result.imports.push(path.to_string_lossy().into_owned());
+            }
+        }
+        Ok(result)
+    }
+
+    /// The config file to use instead of the default one, if any.
+    ///
+    /// In order of precedence: `--config`, `OVERTLS_GUI_CONFIG`, then portable mode.
+    /// The path is made absolute, so that it can be passed on when the app restarts itself, see `args_with_config`.
+    pub fn config_path_override(&self) -> std::io::Result<Option<PathBuf>> {
+        let path = match &self.config {
+            Some(path) => Some(path.clone()),
+            None => std::env::var_os(CONFIG_ENV_VAR).filter(|v| !v.is_empty()).map(PathBuf::from),
+        };
+        let path = match path {
+            Some(path) => Some(path),
+            None => portable_config_path()?,
+        };
+        path.map(std::path::absolute).transpose()
+    }
+}
+
+/// The arguments `args`, without the program name, with `--config` set to `config`.
+/// Elevating through sudo or pkexec drops the environment, so an elevated restart must get the config on the command line.
+pub fn args_with_config(args: impl IntoIterator<Item = OsString>, config: &std::path::Path) -> Vec<String> {
+    let mut result = Vec::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let arg = arg.to_string_lossy().into_owned();
+        if arg == "--config" {
+            args.next();
+        } else if !arg.starts_with("--config=") {
+            result.push(arg);
+        }
+    }
+    result.push("--config".to_string());
+    result.push(config.to_string_lossy().into_owned());
+    result
+}
+
+/// The config path inside the portable data directory, if the portable marker sits next to the executable
+fn portable_config_path() -> std::io::Result<Option<PathBuf>> {
+    let exe = std::env::current_exe()?;
+    let Some(exe_dir) = exe.parent() else {
+        return Ok(None);
+    };
+    if !exe_dir.join(PORTABLE_MARKER).is_file() {
+        return Ok(None);
+    }
+    Ok(Some(exe_dir.join(PORTABLE_DATA_DIR).join("config.json")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> std::io::Result<CliArgs> {
+        CliArgs::parse_from(args.iter().map(OsString::from))
+    }
+
+    #[test]
+    fn parse_config_argument() {
+        let expected = CliArgs {
+            config: Some(PathBuf::from("/tmp/a.json")),
+            ..CliArgs::default()
+        };
+        assert_eq!(parse(&["--config", "/tmp/a.json"]).unwrap(), expected);
+        assert_eq!(parse(&["--config=/tmp/a.json"]).unwrap(), expected);
+        assert_eq!(parse(&[]).unwrap(), CliArgs::default());
+    }
+
+    #[test]
+    fn parse_links_and_files_to_import() {
+        let args = parse(&["ssr://abc", "--config", "/tmp/a.json", "nodes.json"]).unwrap();
+        assert_eq!(args.config, Some(PathBuf::from("/tmp/a.json")));
+        assert_eq!(args.imports.len(), 2);
+        assert_eq!(args.imports[0], "ssr://abc");
+        assert!(std::path::Path::new(&args.imports[1]).is_absolute());
+        assert!(args.imports[1].ends_with("nodes.json"));
+        assert!(parse(&["--register-url-handler"]).unwrap().register_url_handler);
+        assert!(parse(&["--new-instance"]).unwrap().new_instance);
+    }
+
+    #[test]
+    fn parse_rejects_bad_arguments() {
+        assert!(parse(&["--config"]).is_err());
+        assert!(parse(&["--unknown"]).is_err());
+    }
+
+    #[test]
+    fn restart_args_carry_the_config() {
+        let args = ["ssr://abc", "--config", "a.json", "--config=b.json"].map(OsString::from);
+        assert_eq!(
+            args_with_config(args, std::path::Path::new("/tmp/c.json")),
+            vec!["ssr://abc", "--config", "/tmp/c.json"]
+        );
+    }
+
+    #[test]
+    fn config_argument_is_made_absolute() {
+        let args = parse(&["--config", "relative/config.json"]).unwrap();
+        let path = args.config_path_override().unwrap().unwrap();
+        assert!(path.is_absolute());
+        assert!(path.ends_with("relative/config.json"));
+    }
+}
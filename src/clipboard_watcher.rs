@@ -0,0 +1,114 @@
+use crate::paste_operations::{self, ParsedNodes};
+use std::{
+    collections::{HashSet, VecDeque},
+    hash::{DefaultHasher, Hash, Hasher},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+const POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// Fingerprints kept, the oldest are forgotten first
+const MAX_SEEN: usize = 256;
+
+/// Polls the clipboard on a background thread and sends the nodes found in content it hasn't looked at before:
+/// share links, JSON configs or images of QR codes. Whatever is in the clipboard when it starts is ignored.
+/// The thread stops when the watcher is dropped.
+pub struct ClipboardWatcher {
+    stop: Arc<AtomicBool>,
+}
+
+impl ClipboardWatcher {
+    pub fn start(tx: std::sync::mpsc::Sender<ParsedNodes>) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop_thread = stop.clone();
+        std::thread::spawn(move || {
+            let mut clipboard = match arboard::Clipboard::new() {
+                Ok(clipboard) => clipboard,
+                Err(e) => {
+                    log::error!("Clipboard watcher failed to open the clipboard: {e}");
+                    return;
+                }
+            };
+            let mut seen = SeenContents::default();
+            // Parts of split QR codes copied one after the other
+            let mut parts = crate::qr_parts::PartAssembler::default();
+            let mut first = true;
+            while !stop_thread.load(Ordering::Relaxed) {
+                let parsed = if let Ok(text) = clipboard.get_text() {
+                    (seen.is_new(&text) && !first).then(|| paste_operations::parse_links_text(&text))
+                } else if let Ok(img) = clipboard.get_image() {
+                    (seen.is_new(&img.bytes) && !first)
+                        .then(|| paste_operations::clipboard_image_nodes(img, &mut parts).ok())
+                        .flatten()
+                } else {
+                    None
+                };
+                if let Some(parsed) = parsed.filter(|p| !p.nodes.is_empty()) {
+                    log::debug!("Clipboard watcher found {} node(s)", parsed.nodes.len());
+                    if tx.send(parsed).is_err() {
+                        break;
+                    }
+                    fltk::app::awake();
+                }
+                first = false;
+                std::thread::sleep(POLL_INTERVAL);
+            }
+            log::debug!("Clipboard watcher stopped");
+        });
+        ClipboardWatcher { stop }
+    }
+}
+
+impl Drop for ClipboardWatcher {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Remembers the recent clipboard contents by hash
+#[derive(Default)]
+struct SeenContents {
+    hashes: HashSet<u64>,
+    order: VecDeque<u64>,
+}
+
+impl SeenContents {
+    /// Whether `content` is seen for the first time, it's remembered from now on
+    fn is_new<T: Hash + ?Sized>(&mut self, content: &T) -> bool {
+        let mut hasher = DefaultHasher::new();
+        content.hash(&mut hasher);
+        let hash = hasher.finish();
+        if !self.hashes.insert(hash) {
+            return false;
+        }
+        self.order.push_back(hash);
+        if self.order.len() > MAX_SEEN
+            && let Some(oldest) = self.order.pop_front()
+        {
+            self.hashes.remove(&oldest);
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn contents_are_new_only_once() {
+        let mut seen = SeenContents::default();
+        assert!(seen.is_new("ssr://a"));
+        assert!(!seen.is_new("ssr://a"));
+        assert!(seen.is_new("ssr://b"));
+
+        for i in 0..MAX_SEEN {
+            seen.is_new(&format!("filler {i}"));
+        }
+        // Forgotten after enough other contents
+        assert!(seen.is_new("ssr://a"));
+    }
+}
@@ -1,4 +1,8 @@
-use crate::{LOG_HEIGHT, MENUBAR_HEIGHT, OverTlsNode, OverTlsNodeReceivers, node_details_dialog::show_node_details};
+use crate::{
+    ImportSender, LOG_HEIGHT, MENUBAR_HEIGHT, OverTlsNodeReceivers,
+    node_details_dialog::show_node_details,
+    states_manager::{StateEvent, StateStore},
+};
 use fltk::{
     enums::{Align, Color, Event, FrameType, Shortcut},
     menu::MenuFlag,
@@ -6,26 +10,17 @@ use fltk::{
     table::{Table, TableContext},
     window::Window,
 };
-use std::cell::RefCell;
-use std::rc::Rc;
 
 const HEADERS: [&str; 3] = ["Server Host", "Server Port", "Tunnel Path"];
 const ROW_HEADER_WIDTH: i32 = 150;
 
-pub fn create_table(
-    selected_row: &Rc<RefCell<Option<usize>>>,
-    nodes: &Rc<RefCell<Vec<OverTlsNode>>>,
-    win: &Window,
-    node_details_receivers: OverTlsNodeReceivers,
-) -> Table {
+pub fn create_table(store: &StateStore, win: &Window, node_details_receivers: OverTlsNodeReceivers, import_tx: ImportSender) -> Table {
     let mut table = Table::new(0, MENUBAR_HEIGHT, win.w(), win.h() - MENUBAR_HEIGHT - LOG_HEIGHT, "");
     table.set_cols(HEADERS.len() as i32);
     table.set_col_header(true);
     table.set_row_header(true);
 
-    let configs_rc = nodes.clone();
-    // To highlight the selected row
-    let selected_row_handle = selected_row.clone();
+    let store_handle = store.clone();
     let win_clone = win.clone();
     let mut dnd = false;
     let mut released = false;
@@ -44,33 +39,21 @@ pub fn create_table(
             Event::Paste => {
                 if dnd && released {
                     let event_text = fltk::app::event_text();
-                    let nodes_clone = configs_rc.clone();
-                    let mut table = table.clone();
+                    let store = store_handle.clone();
+                    let win = win_clone.clone();
+                    let import_tx = import_tx.clone();
 
                     // we use a timeout to avoid pasting the path into the buffer
                     fltk::app::add_timeout3(0.0, {
                         move |_| {
-                            let mut successful = false;
-                            // Process each line as a potential file path
-                            for line in event_text.lines() {
-                                log::debug!("Pasting file: {line}");
-                                let path: std::path::PathBuf = line.trim().replace("file://", "").into();
-                                match crate::paste_operations::process_inputed_file(&path) {
-                                    Ok(node) => {
-                                        nodes_clone.borrow_mut().push(node);
-                                        successful = true;
-                                    }
-                                    Err(e) => {
-                                        log::warn!("Failed to load file: {e}");
-                                    }
-                                }
-                            }
-
-                            // Update table if any files were loaded
-                            if successful {
-                                table.set_rows(nodes_clone.borrow().len() as i32);
-                                table.redraw();
-                            }
+                            // Each line is a dropped file path
+                            let paths: Vec<std::path::PathBuf> = event_text
+                                .lines()
+                                .filter(|line| !line.trim().is_empty())
+                                .map(|line| line.trim().replace("file://", "").into())
+                                .collect();
+                            log::debug!("Dropped files: {paths:?}");
+                            crate::import_node_files(&store, &win, "Drop Files", &paths, &import_tx);
                         }
                     });
 
@@ -108,16 +91,15 @@ pub fn create_table(
                 // First, clear all selections
                 table.set_selection(-1, -1, -1, -1);
                 // Then select the current row
-                *selected_row_handle.borrow_mut() = Some(row as usize);
                 table.set_selection(row, 0, row, cols - 1);
-                table.redraw();
+                let id = store_handle.state().remote_nodes.get(row as usize).map(|n| n.id);
+                store_handle.select_node(id);
                 return true;
             }
             if table_context == TableContext::ColHeader || table_context == TableContext::None || table_context == TableContext::Table {
                 // Clear selection when clicking on column header or table empty area
                 table.set_selection(-1, -1, -1, -1);
-                *selected_row_handle.borrow_mut() = None;
-                table.redraw();
+                store_handle.select_node(None);
                 return true;
             }
         }
@@ -128,26 +110,33 @@ pub fn create_table(
             let col = table.callback_col();
             let mut menu_btn = fltk::menu::MenuButton::new(fltk::app::event_x(), fltk::app::event_y(), 1, 1, "");
 
-            let count = configs_rc.borrow().len();
+            let count = store_handle.state().remote_nodes.len();
             log::debug!("Right-click context menu, items count = {count}, table context = {table_context:?}, row = {row}, col = {col}");
 
-            if (table_context == TableContext::Cell || table_context == TableContext::RowHeader) && 0 <= row && row < count as i32 {
-                let configs_clone = configs_rc.clone();
+            // Resolve the row now, the menu actions then refer to the node by its id
+            let node_id = match table_context {
+                TableContext::Cell | TableContext::RowHeader if row >= 0 => {
+                    store_handle.state().remote_nodes.get(row as usize).map(|n| n.id)
+                }
+                _ => None,
+            };
+            if let Some(node_id) = node_id {
+                let store = store_handle.clone();
                 let win = win_clone.clone();
                 let node_details_receivers = node_details_receivers.clone();
                 menu_btn.add("View details", Shortcut::None, MenuFlag::Normal, move |_m| {
-                    let cfg = configs_clone.borrow().get(row as usize).cloned();
+                    let cfg = store.state().node(node_id).map(|n| n.node.clone());
                     if let Some(cfg) = cfg {
                         let (tx, rx) = std::sync::mpsc::channel();
                         show_node_details(&win, Some(cfg), tx);
-                        node_details_receivers.lock().unwrap().push((Some(row as usize), rx));
+                        node_details_receivers.lock().unwrap().push((Some(node_id), rx));
                     }
                 });
 
                 // Export Node menu item
-                let configs_clone = configs_rc.clone();
+                let store = store_handle.clone();
                 menu_btn.add("Export Node", Shortcut::None, MenuFlag::Normal, move |_m| {
-                    let Some(cfg) = configs_clone.borrow().get(row as usize).cloned() else {
+                    let Some(cfg) = store.state().node(node_id).map(|n| n.node.clone()) else {
                         return;
                     };
                     let Some(path) = crate::util::file_chooser_save_file("Export Node as JSON", None, "JSON File", &["json"]) else {
@@ -178,10 +167,11 @@ pub fn create_table(
 
                 // Show QR Code menu item
                 let win = win_clone.clone();
-                let configs_clone = configs_rc.clone();
+                let store = store_handle.clone();
                 menu_btn.add("Show QR Code", Shortcut::None, MenuFlag::MenuDivider, move |_m| {
-                    if let Some(cfg) = configs_clone.borrow().get(row as usize)
-                        && let Ok(ssr_url) = cfg.generate_ssr_url()
+                    let cfg = store.state().node(node_id).map(|n| n.node.clone());
+                    if let Some(cfg) = cfg
+                        && let Ok(link) = crate::node_share_link(&store, &cfg)
                     {
                         let name = cfg.remarks.clone().unwrap_or_default();
                         let title = if name.is_empty() {
@@ -189,7 +179,7 @@ pub fn create_table(
                         } else {
                             format!("Node QR Code - '{name}'")
                         };
-                        if let Err(e) = crate::qr_code_dialog::qr_code_dialog(&win, &title, &ssr_url) {
+                        if let Err(e) = crate::qr_code_dialog::qr_code_dialog(&win, &title, &link) {
                             rfd::MessageDialog::new()
                                 .set_title("Error")
                                 .set_description(format!("Failed to show QR code: {e}"))
@@ -199,14 +189,21 @@ pub fn create_table(
                     }
                 });
 
-                let configs_clone = configs_rc.clone();
+                let store = store_handle.clone();
                 let mut table_clone = table.clone();
-                let selected_row_clone = selected_row_handle.clone();
                 menu_btn.add("Delete", Shortcut::None, MenuFlag::MenuDivider, move |_| {
-                    let title = configs_clone
-                        .borrow()
-                        .get(row as usize)
-                        .map(|c| c.remarks.clone().unwrap_or_default())
+                    if store.running_node_id() == Some(node_id) {
+                        rfd::MessageDialog::new()
+                            .set_title("Error")
+                            .set_description("This node is running. Please stop it first.")
+                            .set_level(rfd::MessageLevel::Error)
+                            .show();
+                        return;
+                    }
+                    let title = store
+                        .state()
+                        .node(node_id)
+                        .map(|c| c.node.remarks.clone().unwrap_or_default())
                         .unwrap_or_default();
                     let confirm = rfd::MessageDialog::new()
                         .set_title("Confirm Deletion")
@@ -215,11 +212,8 @@ pub fn create_table(
                         .set_level(rfd::MessageLevel::Warning)
                         .show();
                     if confirm == rfd::MessageDialogResult::Ok {
-                        configs_clone.borrow_mut().remove(row as usize);
-                        table_clone.set_rows(configs_clone.borrow().len() as i32);
                         table_clone.set_selection(-1, -1, -1, -1);
-                        *selected_row_clone.borrow_mut() = None;
-                        table_clone.redraw();
+                        store.update(StateEvent::NodesChanged, |s| s.remove_node(node_id));
                     }
                 });
             }
@@ -241,13 +235,13 @@ pub fn create_table(
             let table_context = table.callback_context();
             if table_context == TableContext::Cell || table_context == TableContext::RowHeader {
                 let row = table.callback_row();
-                if row >= 0 && (row as usize) < configs_rc.borrow().len() {
-                    let cfg = configs_rc.borrow().get(row as usize).cloned();
-                    if let Some(cfg) = cfg {
-                        let (tx, rx) = std::sync::mpsc::channel();
-                        show_node_details(&win_clone, Some(cfg), tx);
-                        node_details_receivers.lock().unwrap().push((Some(row as usize), rx));
-                    }
+                let stored = (row >= 0)
+                    .then(|| store_handle.state().remote_nodes.get(row as usize).cloned())
+                    .flatten();
+                if let Some(stored) = stored {
+                    let (tx, rx) = std::sync::mpsc::channel();
+                    show_node_details(&win_clone, Some(stored.node), tx);
+                    node_details_receivers.lock().unwrap().push((Some(stored.id), rx));
                 }
                 return true;
             }
@@ -255,8 +249,7 @@ pub fn create_table(
         false
     });
 
-    let configs_for_draw = nodes.clone();
-    let selected_row_draw = selected_row.clone();
+    let store_draw = store.clone();
     table.draw_cell(move |_t, ctx, row, col, x, y, w, h| {
         // Set font and size for Table cell explicitly
         // This is necessary because without it, the font might be inconsistent
@@ -272,21 +265,25 @@ pub fn create_table(
             TableContext::RowHeader => {
                 fltk::draw::draw_box(FrameType::ThinUpBox, x, y, w, h, Color::FrameDefault);
                 fltk::draw::set_draw_color(Color::Black);
-                let configs = configs_for_draw.borrow();
-                let text = configs.get(row as usize).and_then(|cfg| cfg.remarks.as_deref()).unwrap_or("");
-                let is_selected = selected_row_draw.borrow().is_some_and(|sel| sel as i32 == row);
+                let state = store_draw.state();
+                let stored = state.remote_nodes.get(row as usize);
+                let text = stored.and_then(|n| n.node.remarks.as_deref()).unwrap_or("");
+                let is_selected = stored.is_some_and(|n| state.current_node_id == Some(n.id));
+                let is_running = stored.is_some_and(|n| store_draw.running_node_id() == Some(n.id));
                 let check = if cfg!(unix) { "✔  " } else { "✔ " };
-                let display_text = format!("{}{text}", if is_selected { check } else { "     " });
+                let running = if is_running { " ▶" } else { "" };
+                let display_text = format!("{}{text}{running}", if is_selected { check } else { "     " });
                 fltk::draw::draw_text2(&display_text, x, y, w, h, Align::Left);
             }
             TableContext::Cell => {
                 // Only highlight the selected row
-                let highlight = selected_row_draw.borrow().is_some_and(|sel| sel as i32 == row);
+                let state = store_draw.state();
+                let stored = state.remote_nodes.get(row as usize);
+                let highlight = stored.is_some_and(|n| state.current_node_id == Some(n.id));
                 let bg = if highlight { Color::Yellow } else { Color::White };
                 fltk::draw::draw_box(FrameType::ThinUpBox, x, y, w, h, bg);
                 fltk::draw::set_draw_color(Color::Black);
-                let configs = configs_for_draw.borrow();
-                if let Some(cfg) = configs.get(row as usize) {
+                if let Some(cfg) = stored.map(|n| &n.node) {
                     let tunnel_path_str = cfg.tunnel_path.to_string();
                     let (host, port) = if let Some(client) = &cfg.client {
                         (client.server_host.as_str(), client.server_port.to_string())
@@ -13,7 +13,9 @@ pub fn merge_system_settings_to_node_config(system_settings: &SystemSettings, no
 
 pub fn restart_as_admin() -> std::io::Result<std::process::ExitStatus> {
     log::debug!("Not running as admin, trying to elevate...");
-    let status = run_as::restart_self_elevated(None, true, false, Some(std::time::Duration::from_secs(10)))?;
+    // The environment doesn't survive the elevation, a config chosen by `OVERTLS_GUI_CONFIG` must be passed explicitly
+    let args = crate::states_manager::config_path_override().map(|path| crate::cli::args_with_config(std::env::args_os().skip(1), &path));
+    let status = run_as::restart_self_elevated(args, true, false, Some(std::time::Duration::from_secs(10)))?;
     Ok(status.unwrap_or_default())
 }
 
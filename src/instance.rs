@@ -0,0 +1,282 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream},
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+/// File in the config directory telling later launches where the running instance listens
+const ENDPOINT_FILE: &str = "instance.json";
+
+/// File in the config directory locked by the running instance, the OS releases it even on a crash
+const LOCK_FILE: &str = "instance.lock";
+
+/// How long a later launch waits for an instance that is starting up to serve requests
+const STARTUP_WAIT: std::time::Duration = std::time::Duration::from_secs(5);
+
+const IO_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// Longest request accepted, a few thousand links
+const MAX_REQUEST_LEN: u64 = 1024 * 1024;
+
+/// What a later launch asks the running instance to do
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum InstanceRequest {
+    /// Bring the main window to the front
+    Activate,
+    /// Import share links and node files given on the command line
+    Import(Vec<String>),
+}
+
+#[derive(Serialize, Deserialize)]
+struct Endpoint {
+    port: u16,
+    /// Secret only readable from the config directory, so other local users can't inject nodes
+    token: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Envelope {
+    token: String,
+    request: InstanceRequest,
+}
+
+/// Hand `request` to the instance running on the config directory `dir`.
+/// `Ok(false)` when no instance answers, the caller should then handle the request itself.
+pub fn send_to_running_instance(dir: &Path, request: InstanceRequest) -> std::io::Result<bool> {
+    let endpoint: Endpoint = match std::fs::read_to_string(dir.join(ENDPOINT_FILE)) {
+        Ok(contents) => serde_json::from_str(&contents).map_err(|e| std::io::Error::other(format!("Invalid {ENDPOINT_FILE}: {e}")))?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e),
+    };
+    let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, endpoint.port));
+    // A stale file left by an instance that crashed
+    let Ok(mut stream) = TcpStream::connect_timeout(&addr, IO_TIMEOUT) else {
+        return Ok(false);
+    };
+    stream.set_read_timeout(Some(IO_TIMEOUT))?;
+    stream.set_write_timeout(Some(IO_TIMEOUT))?;
+    let envelope = Envelope {
+        token: endpoint.token,
+        request,
+    };
+    let mut line = serde_json::to_string(&envelope).map_err(|e| std::io::Error::other(format!("Failed to serialize request: {e}")))?;
+    line.push('\n');
+    stream.write_all(line.as_bytes())?;
+
+    let mut reply = String::new();
+    BufReader::new(stream).read_line(&mut reply)?;
+    if reply.trim() != "ok" {
+        return Err(std::io::Error::other(format!(
+            "The running instance refused the request: {}",
+            reply.trim()
+        )));
+    }
+    Ok(true)
+}
+
+/// Hand `request` to the instance holding the lock on `dir`, waiting a bit for it to serve requests if it's starting up
+pub fn forward_to_running_instance(dir: &Path, request: InstanceRequest) -> std::io::Result<()> {
+    let start = std::time::Instant::now();
+    while !send_to_running_instance(dir, request.clone())? {
+        if start.elapsed() > STARTUP_WAIT {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::TimedOut,
+                "Another instance is running but doesn't answer",
+            ));
+        }
+        std::thread::sleep(std::time::Duration::from_millis(200));
+    }
+    Ok(())
+}
+
+/// The single instance running on a config directory, serving the requests of later launches on a loopback port.
+/// The lock is released and the endpoint file removed on drop.
+pub struct InstanceServer {
+    endpoint_path: PathBuf,
+    token: String,
+    port: u16,
+    stop: Arc<AtomicBool>,
+    // Dropped last, once the endpoint is gone
+    _lock: std::fs::File,
+}
+
+impl InstanceServer {
+    /// Take the lock of the config directory `dir`, start listening and publish the endpoint.
+    /// `None` when another instance holds the lock. `on_request` is called on the listening thread for each authenticated request.
+    pub fn start(dir: &Path, on_request: impl Fn(InstanceRequest) + Send + 'static) -> std::io::Result<Option<Self>> {
+        let lock_path = dir.join(LOCK_FILE);
+        let lock = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&lock_path)?;
+        // Usable by the user once an elevated instance is gone
+        crate::util::give_to_real_user(&lock_path, false)?;
+        match lock.try_lock() {
+            Ok(()) => {}
+            Err(std::fs::TryLockError::WouldBlock) => return Ok(None),
+            Err(std::fs::TryLockError::Error(e)) => return Err(e),
+        }
+
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+        let token = uuid::Uuid::new_v4().simple().to_string();
+        let port = listener.local_addr()?.port();
+        let endpoint = Endpoint {
+            port,
+            token: token.clone(),
+        };
+        let endpoint_path = dir.join(ENDPOINT_FILE);
+        let contents = serde_json::to_string(&endpoint).map_err(|e| std::io::Error::other(format!("Failed to serialize endpoint: {e}")))?;
+        write_private_file(&endpoint_path, &contents)?;
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop_thread = stop.clone();
+        let expected_token = token.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                if stop_thread.load(Ordering::Relaxed) {
+                    break;
+                }
+                let result = stream.and_then(|stream| serve_one(stream, &expected_token));
+                match result {
+                    Ok(request) => on_request(request),
+                    Err(e) => log::warn!("Rejected an instance request: {e}"),
+                }
+            }
+        });
+        Ok(Some(InstanceServer {
+            endpoint_path,
+            token,
+            port,
+            stop,
+            _lock: lock,
+        }))
+    }
+}
+
+impl Drop for InstanceServer {
+    fn drop(&mut self) {
+        // Leave the file alone if another instance took over meanwhile
+        let ours = std::fs::read_to_string(&self.endpoint_path)
+            .ok()
+            .and_then(|contents| serde_json::from_str::<Endpoint>(&contents).ok())
+            .is_some_and(|endpoint| endpoint.token == self.token);
+        if ours {
+            let _ = std::fs::remove_file(&self.endpoint_path);
+        }
+        // Wake the listening thread up so that it closes the port
+        self.stop.store(true, Ordering::Relaxed);
+        let _ = TcpStream::connect_timeout(&SocketAddr::from((Ipv4Addr::LOCALHOST, self.port)), IO_TIMEOUT);
+    }
+}
+
+fn serve_one(stream: TcpStream, expected_token: &str) -> std::io::Result<InstanceRequest> {
+    stream.set_read_timeout(Some(IO_TIMEOUT))?;
+    stream.set_write_timeout(Some(IO_TIMEOUT))?;
+    let mut line = String::new();
+    BufReader::new((&stream).take(MAX_REQUEST_LEN)).read_line(&mut line)?;
+    let reply = |msg: &str| (&stream).write_all(format!("{msg}\n").as_bytes());
+    let envelope = match serde_json::from_str::<Envelope>(&line) {
+        Ok(envelope) if envelope.token == expected_token => envelope,
+        Ok(_) => {
+            reply("invalid token")?;
+            return Err(std::io::Error::new(std::io::ErrorKind::PermissionDenied, "Invalid token"));
+        }
+        Err(e) => {
+            reply("invalid request")?;
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("Invalid request: {e}"),
+            ));
+        }
+    };
+    reply("ok")?;
+    Ok(envelope.request)
+}
+
+/// Write a file only its owner can read, handed over to the user behind an elevation.
+/// Always a fresh file, one left by an older run may be readable by others.
+fn write_private_file(path: &Path, contents: &str) -> std::io::Result<()> {
+    crate::states_manager::write_file_atomically(path, contents.as_bytes())?;
+    crate::util::give_to_real_user(path, false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("overtls-gui-{name}-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn forward_request_to_running_instance() {
+        let dir = temp_dir("instance");
+        let (tx, rx) = std::sync::mpsc::channel();
+        let server = InstanceServer::start(&dir, move |request| tx.send(request).unwrap())
+            .unwrap()
+            .unwrap();
+
+        let request = InstanceRequest::Import(vec!["ssr://abc".to_string()]);
+        assert!(send_to_running_instance(&dir, request).unwrap());
+        assert_eq!(rx.recv().unwrap(), InstanceRequest::Import(vec!["ssr://abc".to_string()]));
+
+        drop(server);
+        assert!(!dir.join(ENDPOINT_FILE).exists());
+        assert!(!send_to_running_instance(&dir, InstanceRequest::Import(Vec::new())).unwrap());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reject_requests_with_a_wrong_token() {
+        let dir = temp_dir("instance-token");
+        let (tx, rx) = std::sync::mpsc::channel();
+        let _server = InstanceServer::start(&dir, move |request| tx.send(request).unwrap())
+            .unwrap()
+            .unwrap();
+
+        let path = dir.join(ENDPOINT_FILE);
+        let mut endpoint: Endpoint = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        endpoint.token = "guessed".to_string();
+        std::fs::write(&path, serde_json::to_string(&endpoint).unwrap()).unwrap();
+
+        assert!(send_to_running_instance(&dir, InstanceRequest::Import(Vec::new())).is_err());
+        assert!(rx.try_recv().is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn endpoint_file_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = temp_dir("instance-private");
+        let path = dir.join(ENDPOINT_FILE);
+        std::fs::write(&path, "left by an older run").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+
+        let _server = InstanceServer::start(&dir, |_| {}).unwrap().unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn only_one_instance_per_config_dir() {
+        let dir = temp_dir("instance-lock");
+        let first = InstanceServer::start(&dir, |_| {}).unwrap();
+        assert!(first.is_some());
+        assert!(InstanceServer::start(&dir, |_| {}).unwrap().is_none());
+        assert!(forward_to_running_instance(&dir, InstanceRequest::Activate).is_ok());
+
+        drop(first);
+        assert!(InstanceServer::start(&dir, |_| {}).unwrap().is_some());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
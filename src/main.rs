@@ -1,34 +1,60 @@
 // To remove the console window on Windows in release mode
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use crate::{content_table::refresh_table, node_details_dialog::show_node_details};
+use crate::{
+    content_table::refresh_table,
+    node_details_dialog::show_node_details,
+    states_manager::{DuplicatePolicy, StateEvent, StateStore},
+};
 use fltk::{
     enums::{Event, Shortcut},
     menu::{MenuBar, MenuFlag},
     prelude::{DisplayExt, GroupExt, MenuExt, WidgetBase, WidgetExt, WindowExt},
     window::Window,
 };
-use std::rc::Rc;
-use std::{cell::RefCell, sync::mpsc::Receiver};
+use std::sync::mpsc::Receiver;
 
 pub(crate) use overtls::Config as OverTlsNode;
 
-pub(crate) type OverTlsNodeReceivers = std::sync::Arc<std::sync::Mutex<Vec<(Option<usize>, Receiver<Option<OverTlsNode>>)>>>;
+pub(crate) type OverTlsNodeReceivers =
+    std::sync::Arc<std::sync::Mutex<Vec<(Option<states_manager::NodeId>, Receiver<Option<OverTlsNode>>)>>>;
+/// Nodes picked in the node selection dialog, imported by the main loop
+pub(crate) type ImportSender = std::sync::mpsc::Sender<paste_operations::ParsedNodes>;
 pub(crate) type BoxError = Box<dyn std::error::Error + Send + Sync + 'static>;
 
+mod backup_archive;
+mod cli;
+mod clipboard_watcher;
 mod content_table;
 mod core;
+mod instance;
 mod logger;
 mod node_details_dialog;
+mod node_selection_dialog;
 mod paste_operations;
 mod qr_code_dialog;
+mod qr_parts;
+mod region_capture;
+mod server_config;
 mod settings_dialog;
+mod share_link;
 mod states_manager;
+mod subscriptions;
 mod util;
+mod vault;
 
 pub(crate) const MENUBAR_HEIGHT: i32 = 30;
 pub(crate) const LOG_HEIGHT: i32 = 240;
 
+/// Seconds between two checks for external changes of config.json
+const CONFIG_CHECK_INTERVAL: f64 = 2.0;
+
+/// Seconds between two checks for subscriptions due for a scheduled refresh
+const SUBSCRIPTION_CHECK_INTERVAL: f64 = 60.0;
+
+/// Subscriptions being fetched, so a slow one isn't fetched twice at once
+type SubscriptionsInFlight = std::rc::Rc<std::cell::RefCell<std::collections::HashSet<subscriptions::SubscriptionId>>>;
+
 #[tokio::main]
 async fn main() -> Result<(), BoxError> {
     // #[cfg(debug_assertions)]
@@ -36,8 +62,79 @@ async fn main() -> Result<(), BoxError> {
 
     let (tx, rx) = std::sync::mpsc::channel();
 
-    let state = states_manager::load_app_state();
-    let system_settings = state.system_settings.clone().unwrap_or_default();
+    let args = match cli::CliArgs::parse() {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{e}");
+            rfd::MessageDialog::new()
+                .set_title("Error")
+                .set_description(e.to_string())
+                .set_level(rfd::MessageLevel::Error)
+                .show();
+            return Ok(());
+        }
+    };
+    if args.help {
+        println!("{}", cli::USAGE);
+        rfd::MessageDialog::new()
+            .set_title("Usage")
+            .set_description(cli::USAGE)
+            .set_level(rfd::MessageLevel::Info)
+            .show();
+        return Ok(());
+    }
+    if args.register_url_handler {
+        match util::register_url_handler() {
+            Ok(path) => println!("Registered {}", path.display()),
+            Err(e) => {
+                eprintln!("{e}");
+                rfd::MessageDialog::new()
+                    .set_title("Error")
+                    .set_description(format!("Failed to register the link handler: {e}"))
+                    .set_level(rfd::MessageLevel::Error)
+                    .show();
+            }
+        }
+        return Ok(());
+    }
+    if let Some(path) = args.config_path_override()? {
+        states_manager::set_config_path_override(path);
+    }
+
+    // One instance per config, later launches hand their links and files over to it and bring it to the front.
+    // Requests wait in the channel until the main loop runs.
+    let (instance_tx, instance_rx) = std::sync::mpsc::channel();
+    let mut instance_server = None;
+    if !args.new_instance {
+        match start_instance_server(&instance_tx) {
+            Ok(Some(server)) => instance_server = Some(server),
+            Ok(None) => {
+                let request = if args.imports.is_empty() {
+                    instance::InstanceRequest::Activate
+                } else {
+                    instance::InstanceRequest::Import(args.imports)
+                };
+                if let Err(e) = instance::forward_to_running_instance(&states_manager::config_dir(), request) {
+                    eprintln!("{e}");
+                    rfd::MessageDialog::new()
+                        .set_title("Error")
+                        .set_description(format!("{e}.\nStart with --new-instance to run another instance anyway."))
+                        .set_level(rfd::MessageLevel::Error)
+                        .show();
+                }
+                return Ok(());
+            }
+            Err(e) => eprintln!("Failed to check for a running instance: {e}"),
+        }
+    }
+    if !args.imports.is_empty() {
+        let _ = instance_tx.send(instance::InstanceRequest::Import(args.imports));
+    }
+
+    let Some(mut state) = load_app_state_or_recover() else {
+        return Ok(());
+    };
+    let system_settings = state.effective_settings();
 
     if let Err(e) = log::set_boxed_logger(Box::new(system_settings.create_logger(tx))) {
         log::warn!("Failed to set logger: {e}");
@@ -46,100 +143,214 @@ async fn main() -> Result<(), BoxError> {
     log::set_max_level(log::LevelFilter::Trace);
 
     let tun2proxy_enable = system_settings.tun2proxy_enable.unwrap_or(false);
-    let state = Rc::new(RefCell::new(state));
 
     if tun2proxy_enable && !run_as::is_elevated() {
+        // The elevated instance takes over
+        drop(instance_server.take());
         let status = core::restart_as_admin()?;
         std::process::exit(status.code().unwrap_or_default());
     }
 
-    let remote_nodes = Rc::new(RefCell::new(state.borrow().remote_nodes.clone()));
-    let current_node_index = Rc::new(RefCell::new(state.borrow().current_node_index));
+    // Unlock only after elevating, so the passphrase is asked once
+    if state.is_locked() && !unlock_app_state(&mut state) {
+        return Ok(());
+    }
+
+    let store = StateStore::new(state);
 
     // Popup window event-driven queue
     let node_details_receivers: OverTlsNodeReceivers = Arc::new(Mutex::new(Vec::new()));
 
     let _app = ::fltk::app::App::default();
 
-    let ws = state.borrow().window.clone();
+    let ws = store.state().window.clone();
     let title = format!("OverTLS clients manager for {}", util::host_os_name());
     let mut win = Window::new(ws.x, ws.y, ws.w, ws.h, title.as_str());
 
     let mut menubar = MenuBar::new(0, 0, ws.w, MENUBAR_HEIGHT, "");
 
-    let mut table = content_table::create_table(&current_node_index, &remote_nodes, &win, node_details_receivers.clone());
+    let (import_tx, import_rx) = std::sync::mpsc::channel();
+    let mut table = content_table::create_table(&store, &win, node_details_receivers.clone(), import_tx.clone());
+
+    refresh_table(&mut table, &mut win, store.state().remote_nodes.len());
 
-    refresh_table(&mut table, &mut win, remote_nodes.borrow().len());
+    let mut table_clone = table.clone();
+    let mut w = win.clone();
+    store.subscribe(move |event, state| match event {
+        StateEvent::NodesChanged | StateEvent::ProfilesChanged => refresh_table(&mut table_clone, &mut w, state.remote_nodes.len()),
+        StateEvent::SelectionChanged | StateEvent::RunningChanged => table_clone.redraw(),
+        StateEvent::SettingsChanged | StateEvent::SubscriptionsChanged | StateEvent::UiChanged => {}
+    });
 
     let (settings_tx, settings_rx) = std::sync::mpsc::channel();
     let w = win.clone();
-    let state_clone = state.clone();
+    let store_clone = store.clone();
     menubar.add("&Main/Settings", Shortcut::None, MenuFlag::MenuDivider, move |_m| {
-        let settings = state_clone.borrow().system_settings.clone().unwrap_or_default();
+        let settings = store_clone.state().effective_settings();
         settings_dialog::show_settings_dialog(&w, &settings, settings_tx.clone());
     });
 
-    let remote_nodes_clone = remote_nodes.clone();
-    let mut table_clone = table.clone();
-    let mut w = win.clone();
+    let store_clone = store.clone();
+    let w = win.clone();
+    let tx = import_tx.clone();
     menubar.add(
         "&Main/Scan QR Code from screen\t",
         Shortcut::Ctrl | 'r',
         MenuFlag::Normal,
-        move |_m| match paste_operations::screenshot_qr_import() {
-            Ok(config) => {
-                remote_nodes_clone.borrow_mut().push(config);
-                refresh_table(&mut table_clone, &mut w, remote_nodes_clone.borrow().len());
+        move |_m| {
+            let result = paste_operations::screenshot_qr_import(&mut store_clone.qr_parts());
+            match result {
+                Ok(parsed) => import_parsed_nodes(&store_clone, &w, "Scan QR Code", parsed, &tx),
+                Err(e) => {
+                    rfd::MessageDialog::new()
+                        .set_title("Error")
+                        .set_description(format!("Failed to import QR Code: {e}"))
+                        .set_level(rfd::MessageLevel::Error)
+                        .show();
+                }
+            }
+        },
+    );
+
+    let (region_tx, region_rx) = std::sync::mpsc::channel();
+    menubar.add(
+        "&Main/Scan QR Code from Region...\t",
+        Shortcut::Ctrl | Shortcut::Shift | 'r',
+        MenuFlag::Normal,
+        move |_m| {
+            if let Err(e) = region_capture::select_region(region_tx.clone()) {
                 rfd::MessageDialog::new()
-                    .set_title("Success")
-                    .set_description("QR Code scanned and imported successfully!")
-                    .set_level(rfd::MessageLevel::Info)
+                    .set_title("Error")
+                    .set_description(format!("Failed to capture the screen: {e}"))
+                    .set_level(rfd::MessageLevel::Error)
                     .show();
             }
+        },
+    );
+
+    let store_clone = store.clone();
+    let w = win.clone();
+    let tx = import_tx.clone();
+    menubar.add("&Main/Import Node Files...\t", Shortcut::Ctrl | 'o', MenuFlag::Normal, move |_m| {
+        let origin_path = store_clone
+            .state()
+            .current_selection_path
+            .clone()
+            .unwrap_or_else(|| dirs::home_dir().unwrap_or_else(|| std::env::current_dir().unwrap()));
+        let exts = paste_operations::IMPORT_FILE_EXTENSIONS;
+        let Some(paths) = util::file_chooser_open_files("Select node files", origin_path.to_str(), "Configs, QR Images, Links", exts)
+        else {
+            return;
+        };
+        if let Some(parent_dir) = paths.first().and_then(|path| path.parent()) {
+            store_clone.update(StateEvent::UiChanged, |s| s.set_current_path(parent_dir));
+        }
+        import_node_files(&store_clone, &w, "Import Node Files", &paths, &tx);
+    });
+
+    let store_clone = store.clone();
+    let w = win.clone();
+    menubar.add("&Main/Backup Everything...", Shortcut::None, MenuFlag::Normal, move |_| {
+        store_clone.update(StateEvent::UiChanged, |s| s.window.refresh_window(&w));
+        let origin_path = store_clone
+            .state()
+            .current_selection_path
+            .clone()
+            .unwrap_or_else(|| dirs::home_dir().unwrap_or_else(|| std::env::current_dir().unwrap()));
+        let Some(path) = util::file_chooser_save_file("Backup Everything", origin_path.to_str(), "JSON File", &["json"]) else {
+            return;
+        };
+        let result = backup_archive::create_archive(&store_clone.state())
+            .and_then(|contents| states_manager::write_file_atomically(&path, contents.as_bytes()));
+        match result {
+            Ok(()) => {
+                states_manager::set_file_owner_if_needed(&path);
+                store_clone.update(StateEvent::UiChanged, |s| s.set_current_path(path.parent().unwrap_or(&origin_path)));
+                log::info!("Backup written to {}", path.display());
+            }
             Err(e) => {
                 rfd::MessageDialog::new()
                     .set_title("Error")
-                    .set_description(format!("Failed to import QR Code: {e}"))
+                    .set_description(format!("Backup failed: {e}"))
                     .set_level(rfd::MessageLevel::Error)
                     .show();
             }
-        },
-    );
+        }
+    });
 
-    let remote_nodes_clone = remote_nodes.clone();
-    let state_clone = state.clone();
-    let mut table_clone = table.clone();
+    let store_clone = store.clone();
     let mut w = win.clone();
-    menubar.add("&Main/Import Node File\t", Shortcut::Ctrl | 'o', MenuFlag::Normal, move |_m| {
-        let origin_path = state_clone
-            .borrow()
+    menubar.add("&Main/Restore from Backup...", Shortcut::None, MenuFlag::MenuDivider, move |_| {
+        let origin_path = store_clone
+            .state()
             .current_selection_path
             .clone()
             .unwrap_or_else(|| dirs::home_dir().unwrap_or_else(|| std::env::current_dir().unwrap()));
-        // Show file chooser dialog and import config file
-        let path = util::file_chooser_open_file("Select config file", origin_path.to_str(), "JSON File", &["json"]);
-        if let Some(path) = &path {
-            match OverTlsNode::from_config_file(path) {
-                Ok(config) => {
-                    if let Some(parent_dir) = std::path::Path::new(path).parent() {
-                        state_clone.borrow_mut().set_current_path(parent_dir);
-                    }
-                    remote_nodes_clone.borrow_mut().push(config);
-                    refresh_table(&mut table_clone, &mut w, remote_nodes_clone.borrow().len());
-                }
-                Err(e) => {
-                    rfd::MessageDialog::new()
-                        .set_title("Error")
-                        .set_description(format!("Import failed: {e}"))
-                        .set_level(rfd::MessageLevel::Error)
-                        .show();
-                }
+        let Some(path) = util::file_chooser_open_file("Restore from Backup", origin_path.to_str(), "JSON File", &["json"]) else {
+            return;
+        };
+        let mut archive = match std::fs::read_to_string(&path).and_then(|contents| backup_archive::read_archive(&contents)) {
+            Ok(archive) => archive,
+            Err(e) => {
+                rfd::MessageDialog::new()
+                    .set_title("Error")
+                    .set_description(format!("Failed to read backup {}: {e}", path.display()))
+                    .set_level(rfd::MessageLevel::Error)
+                    .show();
+                return;
+            }
+        };
+        if archive.is_locked() && !unlock_backup_archive(&store_clone, &mut archive) {
+            return;
+        }
+
+        let preview = backup_archive::restore_preview(&store_clone.state(), &archive);
+        let (replace, merge) = ("Replace".to_string(), "Merge".to_string());
+        let result = rfd::MessageDialog::new()
+            .set_title("Restore from Backup")
+            .set_description(preview)
+            .set_buttons(rfd::MessageButtons::YesNoCancelCustom(
+                replace.clone(),
+                merge.clone(),
+                "Cancel".to_string(),
+            ))
+            .set_level(rfd::MessageLevel::Warning)
+            .show();
+        let replace = match result {
+            rfd::MessageDialogResult::Custom(s) if s == replace => true,
+            rfd::MessageDialogResult::Custom(s) if s == merge => false,
+            rfd::MessageDialogResult::Yes => true,
+            rfd::MessageDialogResult::No => false,
+            _ => return,
+        };
+        // The running node may not exist anymore once replaced, it couldn't be stopped from the table then
+        if replace && store_clone.running_node_id().is_some() {
+            rfd::MessageDialog::new()
+                .set_title("Error")
+                .set_description("A node is running. Please stop it before replacing everything.")
+                .set_level(rfd::MessageLevel::Error)
+                .show();
+            return;
+        }
+        if replace {
+            // The rotating backups keep the replaced state, just in case
+            let vault = store_clone.state().vault.clone();
+            if let Err(e) = states_manager::backup_config_now(vault.as_ref()) {
+                log::warn!("Failed to back up the current config before restoring: {e}");
             }
+            let ws = archive.window.clone();
+            store_clone.update(StateEvent::ProfilesChanged, |s| {
+                s.reload_from(archive);
+                s.window = ws.clone();
+            });
+            w.resize(ws.x, ws.y, ws.w, ws.h);
+            log::info!("Restored everything from {}", path.display());
+        } else {
+            let added = store_clone.update(StateEvent::ProfilesChanged, |s| s.merge_nodes_from(archive));
+            log::info!("Merged {added} node(s) from {}", path.display());
         }
     });
 
-    // let remote_nodes_clone = remote_nodes.clone();
-    // let mut table_clone = table.clone();
     let w = win.clone();
     let node_details_receivers_clone = node_details_receivers.clone();
     menubar.add("&Main/New\t", Shortcut::Ctrl | 'n', MenuFlag::MenuDivider, move |_m| {
@@ -155,13 +366,11 @@ async fn main() -> Result<(), BoxError> {
     let running_token: Arc<Mutex<Option<overtls::CancellationToken>>> = Arc::new(Mutex::new(None));
     let running_handle: Arc<Mutex<Option<std::thread::JoinHandle<std::io::Result<()>>>>> = Arc::new(Mutex::new(None));
 
-    let current_node_index_run = current_node_index.clone();
-    let remote_nodes_run = remote_nodes.clone();
+    let store_clone = store.clone();
     let running_token_run = running_token.clone();
     let running_handle_run = running_handle.clone();
-    let state_clone = state.clone();
     menubar.add("&Main/Run\t", Shortcut::Alt | 'r', MenuFlag::Normal, move |_m| {
-        let Some(idx) = *current_node_index_run.borrow() else {
+        let Some(node_id) = store_clone.state().current_node_id else {
             rfd::MessageDialog::new()
                 .set_title("Error")
                 .set_description("Please select a node first.")
@@ -169,7 +378,7 @@ async fn main() -> Result<(), BoxError> {
                 .show();
             return;
         };
-        let Some(mut config) = remote_nodes_run.borrow().get(idx).cloned() else {
+        let Some(mut config) = store_clone.state().node(node_id).map(|n| n.node.clone()) else {
             rfd::MessageDialog::new()
                 .set_title("Error")
                 .set_description("Selected node not found.")
@@ -187,7 +396,7 @@ async fn main() -> Result<(), BoxError> {
             return;
         }
 
-        let system_settings = state_clone.borrow().system_settings.clone().unwrap_or_default();
+        let system_settings = store_clone.state().effective_settings();
         let tun2proxy_enable = system_settings.tun2proxy_enable.unwrap_or_default();
         if tun2proxy_enable && !run_as::is_elevated() {
             rfd::MessageDialog::new()
@@ -233,15 +442,18 @@ async fn main() -> Result<(), BoxError> {
             res
         });
         *running_handle_run.lock().unwrap() = Some(handle);
+        store_clone.set_running_node_id(Some(node_id));
         log::debug!("Node '{title}' is starting...");
     });
 
     let running_token_stop = running_token.clone();
     let running_handle_stop = running_handle.clone();
+    let store_clone = store.clone();
     menubar.add("&Main/Stop\t", Shortcut::Alt | 's', MenuFlag::MenuDivider, move |_m| {
         if let Err(e) = stop_running_node(&running_token_stop, &running_handle_stop) {
             log::error!("Failed to stop running node: {e}");
         }
+        store_clone.set_running_node_id(None);
     });
 
     fn stop_running_node(
@@ -264,17 +476,82 @@ async fn main() -> Result<(), BoxError> {
         err_info.map(|e| Err(std::io::Error::other(e))).unwrap_or(Ok(()))
     }
 
+    let store_clone = store.clone();
+    menubar.add(
+        "&Main/Master Passphrase/Set or Change...",
+        Shortcut::None,
+        MenuFlag::Normal,
+        move |_| {
+            if !verify_current_passphrase(&store_clone) {
+                return;
+            }
+            let Some(passphrase) = prompt_new_passphrase() else {
+                return;
+            };
+            match vault::Vault::new(&passphrase) {
+                Ok(vault) => {
+                    let previous = store_clone.update(StateEvent::SettingsChanged, |s| s.vault.replace(vault.clone()));
+                    // Seal config.json and every copy of it now, no plaintext or old passphrase copy is left behind
+                    let result = store_clone
+                        .flush()
+                        .and_then(|_| states_manager::reseal_backups(previous.as_ref(), &vault));
+                    match result {
+                        Ok(()) => log::info!("Master passphrase set, nodes and backups are stored encrypted"),
+                        Err(e) => {
+                            rfd::MessageDialog::new()
+                                .set_title("Error")
+                                .set_description(format!("Failed to encrypt the backups of the configuration: {e}"))
+                                .set_level(rfd::MessageLevel::Error)
+                                .show();
+                        }
+                    }
+                }
+                Err(e) => {
+                    rfd::MessageDialog::new()
+                        .set_title("Error")
+                        .set_description(format!("Failed to set passphrase: {e}"))
+                        .set_level(rfd::MessageLevel::Error)
+                        .show();
+                }
+            }
+        },
+    );
+
+    let store_clone = store.clone();
+    menubar.add("&Main/Master Passphrase/Remove", Shortcut::None, MenuFlag::MenuDivider, move |_| {
+        if store_clone.state().vault.is_none() {
+            rfd::MessageDialog::new()
+                .set_title("Master Passphrase")
+                .set_description("No master passphrase is set.")
+                .set_level(rfd::MessageLevel::Info)
+                .show();
+            return;
+        }
+        if !verify_current_passphrase(&store_clone) {
+            return;
+        }
+        let confirm = rfd::MessageDialog::new()
+            .set_title("Remove Master Passphrase")
+            .set_description("Nodes will be stored unencrypted. Continue?")
+            .set_buttons(rfd::MessageButtons::OkCancel)
+            .set_level(rfd::MessageLevel::Warning)
+            .show();
+        if confirm == rfd::MessageDialogResult::Ok {
+            store_clone.update(StateEvent::SettingsChanged, |s| s.vault = None);
+            log::info!("Master passphrase removed");
+        }
+    });
+
     menubar.add("&Main/Quit\t", Shortcut::Ctrl | 'q', MenuFlag::Normal, move |_| {
         ::fltk::app::quit();
     });
 
     // --- Node menu group: View Details ---
-    let current_node_index_clone = current_node_index.clone();
-    let remote_nodes_clone = remote_nodes.clone();
+    let store_clone = store.clone();
     let w = win.clone();
     let node_details_receivers_clone = node_details_receivers.clone();
     menubar.add("&Node/View Details", Shortcut::None, MenuFlag::Normal, move |_menu| {
-        let Some(selected_row) = *current_node_index_clone.borrow() else {
+        let Some(node_id) = store_clone.state().current_node_id else {
             rfd::MessageDialog::new()
                 .set_title("Error")
                 .set_description("No node selected.")
@@ -282,7 +559,7 @@ async fn main() -> Result<(), BoxError> {
                 .show();
             return;
         };
-        let Some(cfg) = remote_nodes_clone.borrow().get(selected_row).cloned() else {
+        let Some(cfg) = store_clone.state().node(node_id).map(|n| n.node.clone()) else {
             rfd::MessageDialog::new()
                 .set_title("Error")
                 .set_description("Selected node not found.")
@@ -292,15 +569,13 @@ async fn main() -> Result<(), BoxError> {
         };
         let (tx, rx) = std::sync::mpsc::channel();
         show_node_details(&w, Some(cfg), tx);
-        node_details_receivers_clone.lock().unwrap().push((Some(selected_row), rx));
+        node_details_receivers_clone.lock().unwrap().push((Some(node_id), rx));
     });
 
     // --- Node menu group: Export Node ---
-    let current_node_index_clone = current_node_index.clone();
-    let remote_nodes_clone = remote_nodes.clone();
-    let state_clone = state.clone();
+    let store_clone = store.clone();
     menubar.add("&Node/Export Node", Shortcut::None, MenuFlag::Normal, move |_menu| {
-        let Some(selected_row) = *current_node_index_clone.borrow() else {
+        let Some(node_id) = store_clone.state().current_node_id else {
             rfd::MessageDialog::new()
                 .set_title("Error")
                 .set_description("No node selected.")
@@ -308,7 +583,7 @@ async fn main() -> Result<(), BoxError> {
                 .show();
             return;
         };
-        let Some(cfg) = remote_nodes_clone.borrow().get(selected_row).cloned() else {
+        let Some(cfg) = store_clone.state().node(node_id).map(|n| n.node.clone()) else {
             rfd::MessageDialog::new()
                 .set_title("Error")
                 .set_description("Selected node not found.")
@@ -316,8 +591,8 @@ async fn main() -> Result<(), BoxError> {
                 .show();
             return;
         };
-        let origin_path = state_clone
-            .borrow()
+        let origin_path = store_clone
+            .state()
             .current_selection_path
             .clone()
             .unwrap_or_else(|| dirs::home_dir().unwrap_or_else(|| std::env::current_dir().unwrap()));
@@ -331,7 +606,7 @@ async fn main() -> Result<(), BoxError> {
                 if std::fs::write(&path, json_str).is_ok() {
                     log::debug!("Node exported to: {}", path.display());
                     states_manager::set_file_owner_if_needed(&path);
-                    state_clone.borrow_mut().set_current_path(path.parent().unwrap_or(&origin_path));
+                    store_clone.update(StateEvent::UiChanged, |s| s.set_current_path(path.parent().unwrap_or(&origin_path)));
                 } else {
                     rfd::MessageDialog::new()
                         .set_title("Error")
@@ -351,11 +626,10 @@ async fn main() -> Result<(), BoxError> {
     });
 
     // --- Node menu group: View QR Code ---
-    let current_node_index_clone = current_node_index.clone();
-    let remote_nodes_clone = remote_nodes.clone();
+    let store_clone = store.clone();
     let w = win.clone();
     menubar.add("&Node/Show QR Code", Shortcut::None, MenuFlag::MenuDivider, move |_menu| {
-        let Some(selected_row) = *current_node_index_clone.borrow() else {
+        let Some(node_id) = store_clone.state().current_node_id else {
             rfd::MessageDialog::new()
                 .set_title("Error")
                 .set_description("No node selected.")
@@ -363,7 +637,7 @@ async fn main() -> Result<(), BoxError> {
                 .show();
             return;
         };
-        let Some(cfg) = remote_nodes_clone.borrow().get(selected_row).cloned() else {
+        let Some(cfg) = store_clone.state().node(node_id).map(|n| n.node.clone()) else {
             rfd::MessageDialog::new()
                 .set_title("Error")
                 .set_description("Selected node not found.")
@@ -371,15 +645,15 @@ async fn main() -> Result<(), BoxError> {
                 .show();
             return;
         };
-        // Generate the SSR URL for the node and display it as a QR code
-        if let Ok(ssr_url) = cfg.generate_ssr_url() {
+        // Generate the share link for the node and display it as a QR code
+        if let Ok(link) = node_share_link(&store_clone, &cfg) {
             let name = cfg.remarks.clone().unwrap_or_default();
             let title = if name.is_empty() {
                 "Node QR Code".to_string()
             } else {
                 format!("Node QR Code - '{name}'")
             };
-            if let Err(e) = qr_code_dialog::qr_code_dialog(&w, &title, &ssr_url) {
+            if let Err(e) = qr_code_dialog::qr_code_dialog(&w, &title, &link) {
                 rfd::MessageDialog::new()
                     .set_title("Error")
                     .set_description(format!("Failed to show QR Code: {e}"))
@@ -396,12 +670,9 @@ async fn main() -> Result<(), BoxError> {
     });
 
     // --- Node menu group: Delete ---
-    let current_node_index_clone = current_node_index.clone();
-    let remote_nodes_clone = remote_nodes.clone();
-    let mut table_clone = table.clone();
-    let mut w = win.clone();
+    let store_clone = store.clone();
     menubar.add("&Node/Delete", Shortcut::None, MenuFlag::MenuDivider, move |_menu| {
-        let Some(selected_row) = *current_node_index_clone.borrow() else {
+        let Some(node_id) = store_clone.state().current_node_id else {
             rfd::MessageDialog::new()
                 .set_title("Error")
                 .set_description("No node selected.")
@@ -409,19 +680,26 @@ async fn main() -> Result<(), BoxError> {
                 .show();
             return;
         };
-        if selected_row > remote_nodes_clone.borrow().len() {
+        let Some(title) = store_clone
+            .state()
+            .node(node_id)
+            .map(|n| n.node.remarks.clone().unwrap_or_default())
+        else {
             rfd::MessageDialog::new()
                 .set_title("Error")
                 .set_description("Selected node not found.")
                 .set_level(rfd::MessageLevel::Error)
                 .show();
             return;
+        };
+        if store_clone.running_node_id() == Some(node_id) {
+            rfd::MessageDialog::new()
+                .set_title("Error")
+                .set_description("This node is running. Please stop it first.")
+                .set_level(rfd::MessageLevel::Error)
+                .show();
+            return;
         }
-        let title = remote_nodes_clone
-            .borrow()
-            .get(selected_row)
-            .map(|c| c.remarks.clone().unwrap_or_default())
-            .unwrap_or_default();
         let confirm = rfd::MessageDialog::new()
             .set_title("Confirm Deletion")
             .set_description(format!("Are you sure you want to delete node: '{title}'?"))
@@ -429,16 +707,13 @@ async fn main() -> Result<(), BoxError> {
             .set_level(rfd::MessageLevel::Warning)
             .show();
         if confirm == rfd::MessageDialogResult::Ok {
-            remote_nodes_clone.borrow_mut().remove(selected_row);
-            *current_node_index_clone.borrow_mut() = None;
-            refresh_table(&mut table_clone, &mut w, remote_nodes_clone.borrow().len());
+            store_clone.update(StateEvent::NodesChanged, |s| s.remove_node(node_id));
         }
     });
 
-    let current_node_index_clone = current_node_index.clone();
-    let remote_nodes_clone = remote_nodes.clone();
+    let store_clone = store.clone();
     menubar.add("&Node/Copy\t", Shortcut::Ctrl | 'c', MenuFlag::Normal, move |_menu| {
-        let Some(selected_row) = *current_node_index_clone.borrow() else {
+        let Some(node_id) = store_clone.state().current_node_id else {
             rfd::MessageDialog::new()
                 .set_title("Error")
                 .set_description("No node selected.")
@@ -446,7 +721,7 @@ async fn main() -> Result<(), BoxError> {
                 .show();
             return;
         };
-        let Some(node) = remote_nodes_clone.borrow().get(selected_row).cloned() else {
+        let Some(node) = store_clone.state().node(node_id).map(|n| n.node.clone()) else {
             rfd::MessageDialog::new()
                 .set_title("Error")
                 .set_description("Selected node not found.")
@@ -454,7 +729,7 @@ async fn main() -> Result<(), BoxError> {
                 .show();
             return;
         };
-        if let Ok(text) = &node.generate_ssr_url() {
+        if let Ok(text) = &node_share_link(&store_clone, &node) {
             ::fltk::app::copy(text);
             let name = node.remarks.clone().unwrap_or_default();
             rfd::MessageDialog::new()
@@ -471,22 +746,333 @@ async fn main() -> Result<(), BoxError> {
         }
     });
 
-    let remote_nodes_clone = remote_nodes.clone();
-    let mut table_clone = table.clone();
-    let mut w = win.clone();
+    let store_clone = store.clone();
+    let w = win.clone();
+    let tx = import_tx.clone();
     menubar.add("&Node/Paste\t", Shortcut::Ctrl | 'v', MenuFlag::Normal, move |_menu| {
-        if let Ok(config) = paste_operations::paste() {
-            remote_nodes_clone.borrow_mut().push(config);
-            refresh_table(&mut table_clone, &mut w, remote_nodes_clone.borrow().len());
+        let result = paste_operations::paste(&mut store_clone.qr_parts());
+        match result {
+            Ok(parsed) => import_parsed_nodes(&store_clone, &w, "Paste", parsed, &tx),
+            Err(e) => {
+                log::debug!("Paste failed: {e}");
+                rfd::MessageDialog::new()
+                    .set_title("Paste")
+                    .set_description("No valid configuration found in clipboard.")
+                    .set_level(rfd::MessageLevel::Warning)
+                    .show();
+            }
+        }
+    });
+
+    let store_clone = store.clone();
+    menubar.add("&Node/Find Duplicates...", Shortcut::None, MenuFlag::Normal, move |_menu| {
+        let groups = store_clone.state().duplicate_groups();
+        if groups.is_empty() {
+            rfd::MessageDialog::new()
+                .set_title("Find Duplicates")
+                .set_description("No duplicate nodes found.")
+                .set_level(rfd::MessageLevel::Info)
+                .show();
+            return;
+        }
+        let listing = {
+            let state = store_clone.state();
+            groups
+                .iter()
+                .map(|group| {
+                    let names: Vec<_> = group
+                        .iter()
+                        .map(|&i| node_selection_dialog::node_summary(&state.remote_nodes[i].node))
+                        .collect();
+                    format!("- {}", names.join(" = "))
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+        let extra: usize = groups.iter().map(|group| group.len() - 1).sum();
+        let confirm = rfd::MessageDialog::new()
+            .set_title("Find Duplicates")
+            .set_description(format!(
+                "{} server(s) are listed more than once:\n{listing}\n\nRemove the {extra} extra node(s), keeping one of each?",
+                groups.len()
+            ))
+            .set_buttons(rfd::MessageButtons::OkCancel)
+            .set_level(rfd::MessageLevel::Warning)
+            .show();
+        if confirm == rfd::MessageDialogResult::Ok {
+            // Never remove the running node, then prefer the selected one
+            let preferred: Vec<_> = [store_clone.running_node_id(), store_clone.state().current_node_id]
+                .into_iter()
+                .flatten()
+                .collect();
+            let removed = store_clone.update(StateEvent::NodesChanged, |s| s.remove_duplicate_nodes(&preferred));
+            log::info!("Removed {removed} duplicate node(s)");
+        }
+    });
+
+    // --- Profile menu group ---
+    let store_clone = store.clone();
+    menubar.add("&Profile/New...", Shortcut::None, MenuFlag::Normal, move |_| {
+        if let Some(name) = prompt_profile_name("New profile name:", "")
+            && let Err(e) = store_clone.update(StateEvent::ProfilesChanged, |s| s.create_profile(&name))
+        {
+            show_profile_error(&e);
+        }
+    });
+
+    let store_clone = store.clone();
+    menubar.add("&Profile/Rename...", Shortcut::None, MenuFlag::Normal, move |_| {
+        let active = store_clone.state().active_profile.clone();
+        if let Some(name) = prompt_profile_name("Rename the current profile to:", &active)
+            && name != active
+            && let Err(e) = store_clone.update(StateEvent::ProfilesChanged, |s| s.rename_profile(&active, &name))
+        {
+            show_profile_error(&e);
+        }
+    });
+
+    let store_clone = store.clone();
+    menubar.add("&Profile/Clone...", Shortcut::None, MenuFlag::Normal, move |_| {
+        let active = store_clone.state().active_profile.clone();
+        if let Some(name) = prompt_profile_name("Name of the copy of the current profile:", &format!("{active} copy"))
+            && let Err(e) = store_clone.update(StateEvent::ProfilesChanged, |s| s.clone_profile(&active, &name))
+        {
+            show_profile_error(&e);
+        }
+    });
+
+    let store_clone = store.clone();
+    menubar.add("&Profile/Delete...", Shortcut::None, MenuFlag::MenuDivider, move |_| {
+        let state = store_clone.state();
+        let others: Vec<String> = state.profiles.iter().map(|p| p.name.clone()).collect();
+        drop(state);
+        if others.is_empty() {
+            rfd::MessageDialog::new()
+                .set_title("Delete Profile")
+                .set_description("There is no other profile. The current profile can't be deleted, switch to another one first.")
+                .set_level(rfd::MessageLevel::Info)
+                .show();
+            return;
+        }
+        let prompt = format!("Profile to delete ({}):", others.join(", "));
+        let Some(name) = fltk::dialog::input_default(&prompt, &others[0]) else {
+            return;
+        };
+        let confirm = rfd::MessageDialog::new()
+            .set_title("Delete Profile")
+            .set_description(format!("Delete profile '{name}' and all its nodes?"))
+            .set_buttons(rfd::MessageButtons::YesNo)
+            .set_level(rfd::MessageLevel::Warning)
+            .show();
+        if confirm == rfd::MessageDialogResult::Yes
+            && let Err(e) = store_clone.update(StateEvent::ProfilesChanged, |s| s.delete_profile(&name))
+        {
+            show_profile_error(&e);
+        }
+    });
+
+    let store_clone = store.clone();
+    menubar.add(
+        "&Profile/Use Own Settings",
+        Shortcut::None,
+        MenuFlag::Toggle | MenuFlag::MenuDivider,
+        move |m| {
+            let own = m.mvalue().is_some_and(|item| item.value());
+            if !own && store_clone.state().profile_settings.is_some() {
+                let confirm = rfd::MessageDialog::new()
+                    .set_title("Use Own Settings")
+                    .set_description("The settings of this profile will be discarded, it will use the global settings again. Continue?")
+                    .set_buttons(rfd::MessageButtons::OkCancel)
+                    .set_level(rfd::MessageLevel::Warning)
+                    .show();
+                if confirm != rfd::MessageDialogResult::Ok {
+                    if let Some(mut item) = m.mvalue() {
+                        item.set();
+                    }
+                    return;
+                }
+            }
+            store_clone.update(StateEvent::SettingsChanged, |s| {
+                s.profile_settings = own.then(|| s.effective_settings());
+            });
+        },
+    );
+
+    let store_clone = store.clone();
+    let running_token_switch = running_token.clone();
+    let running_handle_switch = running_handle.clone();
+    let switch_profile: std::rc::Rc<dyn Fn(&str)> = std::rc::Rc::new(move |name| {
+        if store_clone.state().active_profile == name {
+            return;
+        }
+        // The running node belongs to the profile being left
+        if store_clone.running_node_id().is_some() {
+            if let Err(e) = stop_running_node(&running_token_switch, &running_handle_switch) {
+                log::error!("Failed to stop running node: {e}");
+            }
+            store_clone.set_running_node_id(None);
+        }
+        match store_clone.update(StateEvent::ProfilesChanged, |s| s.switch_profile(name)) {
+            Ok(()) => log::info!("Switched to profile '{name}'"),
+            Err(e) => show_profile_error(&e),
+        }
+    });
+
+    rebuild_profile_menu(&mut menubar, &store, &switch_profile);
+    let menubar_clone = menubar.clone();
+    let store_clone = store.clone();
+    store.subscribe(move |event, _state| {
+        if event == StateEvent::ProfilesChanged {
+            // Never rebuild the menu from within one of its own callbacks
+            let (mut menubar, store, switch_profile) = (menubar_clone.clone(), store_clone.clone(), switch_profile.clone());
+            ::fltk::app::add_timeout3(0.0, move |_| rebuild_profile_menu(&mut menubar, &store, &switch_profile));
+        }
+    });
+
+    // Pick up changes made to config.json by other programs, e.g. provisioning scripts
+    let store_clone = store.clone();
+    ::fltk::app::add_timeout3(CONFIG_CHECK_INTERVAL, move |handle| {
+        check_external_config_change(&store_clone);
+        ::fltk::app::repeat_timeout3(CONFIG_CHECK_INTERVAL, handle);
+    });
+
+    // --- Subscriptions menu group ---
+    let (subscription_tx, subscription_rx) = std::sync::mpsc::channel();
+    let subscriptions_in_flight: SubscriptionsInFlight = Default::default();
+
+    let store_clone = store.clone();
+    let in_flight = subscriptions_in_flight.clone();
+    let tx = subscription_tx.clone();
+    menubar.add("&Subscriptions/Add...", Shortcut::None, MenuFlag::Normal, move |_| {
+        let Some(name) = fltk::dialog::input_default("Subscription name:", "").map(|s| s.trim().to_string()) else {
+            return;
+        };
+        let Some(url) = fltk::dialog::input_default("Subscription URL:", "").map(|s| s.trim().to_string()) else {
+            return;
+        };
+        if name.is_empty() || !(url.starts_with("http://") || url.starts_with("https://")) {
+            rfd::MessageDialog::new()
+                .set_title("Error")
+                .set_description("A subscription needs a name and an http:// or https:// URL.")
+                .set_level(rfd::MessageLevel::Error)
+                .show();
+            return;
+        }
+        let default_interval = subscriptions::DEFAULT_REFRESH_INTERVAL_MINUTES.to_string();
+        let Some(interval) = fltk::dialog::input_default("Refresh interval in minutes, 0 for manual only:", &default_interval) else {
+            return;
+        };
+        let Ok(interval) = interval.trim().parse::<u32>() else {
+            rfd::MessageDialog::new()
+                .set_title("Error")
+                .set_description("The refresh interval must be a number of minutes.")
+                .set_level(rfd::MessageLevel::Error)
+                .show();
+            return;
+        };
+        let subscription = subscriptions::Subscription::new(&name, &url, interval);
+        let id = subscription.id;
+        store_clone.update(StateEvent::SubscriptionsChanged, |s| s.subscriptions.push(subscription));
+        refresh_subscriptions(&store_clone, &in_flight, &tx, |s| s.id == id);
+    });
+
+    let store_clone = store.clone();
+    let in_flight = subscriptions_in_flight.clone();
+    let tx = subscription_tx.clone();
+    menubar.add("&Subscriptions/Refresh All\t", Shortcut::Ctrl | 'u', MenuFlag::Normal, move |_| {
+        if store_clone.state().subscriptions.is_empty() {
+            rfd::MessageDialog::new()
+                .set_title("Subscriptions")
+                .set_description("The current profile has no subscriptions.")
+                .set_level(rfd::MessageLevel::Info)
+                .show();
+            return;
+        }
+        refresh_subscriptions(&store_clone, &in_flight, &tx, |_| true);
+    });
+
+    let store_clone = store.clone();
+    menubar.add("&Subscriptions/Show Status", Shortcut::None, MenuFlag::Normal, move |_| {
+        let state = store_clone.state();
+        let lines: Vec<String> = state
+            .subscriptions
+            .iter()
+            .map(|sub| {
+                let count = state.remote_nodes.iter().filter(|n| n.subscription_id == Some(sub.id)).count();
+                let updated = sub
+                    .last_update
+                    .map(|t| t.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M").to_string())
+                    .unwrap_or_else(|| "never".to_string());
+                let mut line = format!("{}: {count} nodes, updated {updated}\n{}", sub.name, sub.url);
+                if let Some(e) = &sub.last_error {
+                    line.push_str(&format!("\nLast error: {e}"));
+                }
+                line
+            })
+            .collect();
+        drop(state);
+        let description = if lines.is_empty() {
+            "The current profile has no subscriptions.".to_string()
         } else {
+            lines.join("\n\n")
+        };
+        rfd::MessageDialog::new()
+            .set_title("Subscriptions")
+            .set_description(description)
+            .set_level(rfd::MessageLevel::Info)
+            .show();
+    });
+
+    let store_clone = store.clone();
+    menubar.add("&Subscriptions/Remove...", Shortcut::None, MenuFlag::Normal, move |_| {
+        let names: Vec<String> = store_clone.state().subscriptions.iter().map(|s| s.name.clone()).collect();
+        if names.is_empty() {
             rfd::MessageDialog::new()
-                .set_title("Paste")
-                .set_description("No valid configuration found in clipboard.")
-                .set_level(rfd::MessageLevel::Warning)
+                .set_title("Subscriptions")
+                .set_description("The current profile has no subscriptions.")
+                .set_level(rfd::MessageLevel::Info)
                 .show();
+            return;
+        }
+        let prompt = format!("Subscription to remove ({}):", names.join(", "));
+        let Some(name) = fltk::dialog::input_default(&prompt, &names[0]) else {
+            return;
+        };
+        let Some(id) = store_clone
+            .state()
+            .subscriptions
+            .iter()
+            .find(|s| s.name == name.trim())
+            .map(|s| s.id)
+        else {
+            rfd::MessageDialog::new()
+                .set_title("Error")
+                .set_description(format!("Subscription '{name}' not found."))
+                .set_level(rfd::MessageLevel::Error)
+                .show();
+            return;
+        };
+        let confirm = rfd::MessageDialog::new()
+            .set_title("Remove Subscription")
+            .set_description(format!("Remove subscription '{name}' and its nodes?"))
+            .set_buttons(rfd::MessageButtons::YesNo)
+            .set_level(rfd::MessageLevel::Warning)
+            .show();
+        if confirm == rfd::MessageDialogResult::Yes {
+            store_clone.update(StateEvent::NodesChanged, |s| s.remove_subscription(id));
         }
     });
 
+    // Scheduled refreshes, the first check shortly after startup
+    let store_clone = store.clone();
+    let in_flight = subscriptions_in_flight.clone();
+    let tx = subscription_tx.clone();
+    ::fltk::app::add_timeout3(5.0, move |handle| {
+        let now = chrono::Utc::now();
+        refresh_subscriptions(&store_clone, &in_flight, &tx, |s| s.is_due(now));
+        ::fltk::app::repeat_timeout3(SUBSCRIPTION_CHECK_INTERVAL, handle);
+    });
+
     menubar.add("&Help/About", Shortcut::None, MenuFlag::Normal, move |_| {
         let v = env!("CARGO_PKG_VERSION");
         use chrono::Datelike;
@@ -624,6 +1210,10 @@ async fn main() -> Result<(), BoxError> {
         }
     });
 
+    // Offers the nodes copied to the clipboard while enabled in the settings
+    let (clipboard_tx, clipboard_rx) = std::sync::mpsc::channel();
+    let mut clipboard_watch: Option<clipboard_watcher::ClipboardWatcher> = None;
+
     while ::fltk::app::wait() {
         fn handle_menu_event(event: &tray_icon::menu::MenuEvent, win: &mut Window) {
             log::debug!("Tray event received: {event:?}");
@@ -650,19 +1240,21 @@ async fn main() -> Result<(), BoxError> {
         // Deal with settings dialog results
         while let Ok(new_settings) = settings_rx.try_recv() {
             let tun2proxy_enable = new_settings.tun2proxy_enable.unwrap_or_default();
-            let equal_log_level = state
-                .borrow()
-                .system_settings
-                .as_ref()
-                .map(|s| s.is_log_level_equal(&new_settings))
-                .unwrap_or(false);
-            state.borrow_mut().system_settings = Some(new_settings);
+            let equal_log_level = store.state().effective_settings().is_log_level_equal(&new_settings);
+            store.update(StateEvent::SettingsChanged, |s| s.set_effective_settings(new_settings));
             if tun2proxy_enable && !run_as::is_elevated() {
-                save_final_app_state(&state, &remote_nodes, &win, &current_node_index)?;
+                save_final_app_state(&store, &win)?;
+                let serving = instance_server.take().is_some();
                 if let Ok(status) = core::restart_as_admin() {
                     log::debug!("Restarted as admin with status code {status}, exiting current instance.");
                     ::fltk::app::quit();
                 } else {
+                    if serving {
+                        instance_server = start_instance_server(&instance_tx)
+                            .map_err(|e| log::warn!("Failed to serve later launches again: {e}"))
+                            .ok()
+                            .flatten();
+                    }
                     rfd::MessageDialog::new()
                         .set_title("Error")
                         .set_description("Failed to restart as admin.")
@@ -675,7 +1267,9 @@ async fn main() -> Result<(), BoxError> {
                     .set_description("Log level changes will take effect after restart.")
                     .set_level(rfd::MessageLevel::Info)
                     .show();
-                save_final_app_state(&state, &remote_nodes, &win, &current_node_index)?;
+                save_final_app_state(&store, &win)?;
+                // Otherwise the new process would only bring this one to the front
+                drop(instance_server.take());
                 if let Err(e) = run_as::restart_self(None, false) {
                     log::error!("Failed to restart self: {e}");
                 }
@@ -683,16 +1277,106 @@ async fn main() -> Result<(), BoxError> {
             }
         }
 
+        // Start or stop watching the clipboard, the setting may come with a profile switch too
+        let watch_clipboard = store.state().effective_settings().watch_clipboard.unwrap_or_default();
+        if watch_clipboard != clipboard_watch.is_some() {
+            clipboard_watch = watch_clipboard.then(|| clipboard_watcher::ClipboardWatcher::start(clipboard_tx.clone()));
+        }
+
+        // Offer the nodes copied to the clipboard, unless they are all in the list already, e.g. copied from here
+        while let Ok(parsed) = clipboard_rx.try_recv() {
+            if store.state().count_duplicates(&parsed.nodes) == parsed.nodes.len() {
+                log::debug!("Clipboard holds no new node");
+                continue;
+            }
+            let parsed = resolve_server_configs(parsed);
+            node_selection_dialog::show_node_selection_dialog(&win, "Import from Clipboard", parsed, import_tx.clone());
+        }
+
+        // The node thread clears the token when it exits on its own
+        if store.running_node_id().is_some() && running_token.lock().unwrap().is_none() {
+            store.set_running_node_id(None);
+        }
+
+        // Decode the regions selected for QR scanning
+        while let Ok(region) = region_rx.try_recv() {
+            let result = paste_operations::region_qr_import(&region, &mut store.qr_parts());
+            match result {
+                Ok(parsed) => import_parsed_nodes(&store, &win, "Scan Region", parsed, &import_tx),
+                Err(e) => {
+                    rfd::MessageDialog::new()
+                        .set_title("Error")
+                        .set_description(format!("Failed to import QR Code: {e}"))
+                        .set_level(rfd::MessageLevel::Error)
+                        .show();
+                }
+            }
+        }
+
+        // Import what was given on the command line, here or to a later launch
+        while let Ok(request) = instance_rx.try_recv() {
+            match request {
+                instance::InstanceRequest::Activate => win.show(),
+                instance::InstanceRequest::Import(items) => {
+                    win.show();
+                    let parsed = paste_operations::parse_import_args(&items, &mut store.qr_parts());
+                    import_parsed_nodes(&store, &win, "Import", parsed, &import_tx);
+                }
+            }
+        }
+
+        // Import the nodes picked in node selection dialogs
+        while let Ok(parsed) = import_rx.try_recv() {
+            add_imported_nodes(&store, "Import", parsed);
+        }
+
+        // Apply fetched subscriptions
+        while let Ok(outcome) = subscription_rx.try_recv() {
+            subscriptions_in_flight.borrow_mut().remove(&outcome.id);
+            let name = store
+                .state()
+                .profile_subscription(&outcome.profile, outcome.id)
+                .map(|s| s.name.clone());
+            let Some(name) = name else {
+                log::debug!("Dropping the refresh of a subscription or profile removed meanwhile");
+                continue;
+            };
+            match outcome.result {
+                Ok(fetched) => {
+                    let applied = store.update(StateEvent::NodesChanged, |s| {
+                        s.apply_subscription_fetch(&outcome.profile, outcome.id, fetched.nodes)
+                    });
+                    let Some((added, removed)) = applied else {
+                        continue;
+                    };
+                    log::info!(
+                        "Subscription '{name}' refreshed: {added} added, {removed} removed, {} invalid entries skipped",
+                        fetched.rejected
+                    );
+                }
+                Err(e) => {
+                    log::warn!("Failed to refresh subscription '{name}': {e}");
+                    store.update(StateEvent::SubscriptionsChanged, |s| {
+                        s.set_subscription_error(&outcome.profile, outcome.id, e.to_string())
+                    });
+                }
+            }
+        }
+
         // Handle results from node details dialogs
-        node_details_receivers.lock().unwrap().retain(|(row_opt, rx)| {
+        node_details_receivers.lock().unwrap().retain(|(id_opt, rx)| {
             match rx.try_recv() {
                 Ok(Some(details)) => {
-                    if let Some(row) = row_opt {
-                        remote_nodes.borrow_mut()[*row] = details; // Editing existing node
-                    } else {
-                        remote_nodes.borrow_mut().push(details); // New node
-                    }
-                    refresh_table(&mut table, &mut win, remote_nodes.borrow().len());
+                    store.update(StateEvent::NodesChanged, |s| {
+                        if let Some(stored) = id_opt.and_then(|id| s.node_mut(id)) {
+                            stored.node = details; // Editing existing node
+                        } else {
+                            if id_opt.is_some() {
+                                log::warn!("The edited node was deleted meanwhile, adding it as a new node");
+                            }
+                            s.add_node(details); // New node
+                        }
+                    });
                     false // remove
                 }
                 Ok(None) => false,                                 // user cancelled, remove
@@ -741,7 +1425,7 @@ async fn main() -> Result<(), BoxError> {
                     style_buffer.set_text(new_style);
                 }
                 log_display.set_highlight_data(style_buffer.clone(), style_table);
-                if state.borrow().system_settings.as_ref().unwrap().log_auto_scroll.unwrap_or(true) {
+                if store.state().effective_settings().log_auto_scroll.unwrap_or(true) {
                     let lines = log_buffer.count_lines(0, log_buffer.length());
                     log_display.scroll(lines, 0);
                 }
@@ -749,21 +1433,14 @@ async fn main() -> Result<(), BoxError> {
         }
     }
 
-    fn save_final_app_state(
-        state: &Rc<RefCell<states_manager::AppState>>,
-        remote_nodes: &Rc<RefCell<Vec<OverTlsNode>>>,
-        win: &Window,
-        current_node_index: &Rc<RefCell<Option<usize>>>,
-    ) -> std::io::Result<()> {
-        state.borrow_mut().remote_nodes = remote_nodes.borrow().clone();
-        state.borrow_mut().window.refresh_window(win);
-        state.borrow_mut().current_node_index = *current_node_index.borrow();
-
-        states_manager::save_app_state(&state.borrow())?;
-        Ok(())
+    fn save_final_app_state(store: &StateStore, win: &Window) -> std::io::Result<()> {
+        store.update(StateEvent::UiChanged, |s| s.window.refresh_window(win));
+        store.flush()
     }
 
-    save_final_app_state(&state, &remote_nodes, &win, &current_node_index)?;
+    // Don't clobber a change made since the last check
+    check_external_config_change(&store);
+    save_final_app_state(&store, &win)?;
 
     if let Err(e) = stop_running_node(&running_token, &running_handle) {
         log::debug!("Failed to stop running node: {e}");
@@ -771,3 +1448,422 @@ async fn main() -> Result<(), BoxError> {
 
     Ok(())
 }
+
+/// Reload config.json if another program changed it, asking first when there are unsaved edits in memory
+fn check_external_config_change(store: &StateStore) {
+    use states_manager::ExternalChangeResolution;
+    let change = match store.poll_external_change() {
+        Ok(Some(change)) => change,
+        Ok(None) => return,
+        Err(e) => {
+            log::warn!("Ignoring the changed config file: {e}");
+            return;
+        }
+    };
+    if !store.is_dirty() {
+        store.apply_external_change(change, ExternalChangeResolution::Reload);
+        log::info!("Config file changed on disk, reloaded");
+        return;
+    }
+    let (reload, merge, keep) = ("Reload".to_string(), "Merge".to_string(), "Keep Mine".to_string());
+    let result = rfd::MessageDialog::new()
+        .set_title("Config File Changed")
+        .set_description(
+            "The config file was changed by another program while you have unsaved changes.\n\n\
+             Reload: discard your changes and use the file.\n\
+             Merge: keep your changes and add the nodes only found in the file.\n\
+             Keep Mine: overwrite the file with your changes.",
+        )
+        .set_buttons(rfd::MessageButtons::YesNoCancelCustom(reload.clone(), merge.clone(), keep))
+        .set_level(rfd::MessageLevel::Warning)
+        .show();
+    let resolution = match result {
+        rfd::MessageDialogResult::Custom(s) if s == reload => ExternalChangeResolution::Reload,
+        rfd::MessageDialogResult::Custom(s) if s == merge => ExternalChangeResolution::Merge,
+        rfd::MessageDialogResult::Yes => ExternalChangeResolution::Reload,
+        rfd::MessageDialogResult::No => ExternalChangeResolution::Merge,
+        _ => ExternalChangeResolution::KeepMine,
+    };
+    log::info!("Config file changed on disk, resolved with {resolution:?}");
+    store.apply_external_change(change, resolution);
+}
+
+/// Serve the requests of later launches on the config in use, `None` when another instance already does
+fn start_instance_server(tx: &std::sync::mpsc::Sender<instance::InstanceRequest>) -> std::io::Result<Option<instance::InstanceServer>> {
+    let tx = tx.clone();
+    instance::InstanceServer::start(&states_manager::config_dir(), move |request| {
+        if tx.send(request).is_ok() {
+            fltk::app::awake();
+        }
+    })
+}
+
+/// Import nodes found by a paste or a scan. When there are several, let the user pick them first.
+fn import_parsed_nodes(store: &StateStore, win: &Window, title: &str, parsed: paste_operations::ParsedNodes, tx: &ImportSender) {
+    let parsed = resolve_server_configs(parsed);
+    if parsed.nodes.len() > 1 {
+        node_selection_dialog::show_node_selection_dialog(win, title, parsed, tx.clone());
+    } else {
+        add_imported_nodes(store, title, parsed);
+    }
+}
+
+/// Link of `node` in the share format chosen in the settings
+fn node_share_link(store: &StateStore, node: &OverTlsNode) -> std::io::Result<String> {
+    let format = store.state().effective_settings().share_link_format.unwrap_or_default();
+    share_link::generate_share_link(node, format)
+}
+
+/// Import files picked or dropped by the user, previewing the nodes found in them before anything is added
+fn import_node_files(store: &StateStore, win: &Window, title: &str, paths: &[std::path::PathBuf], tx: &ImportSender) {
+    let parsed = paste_operations::parse_files(paths, &mut store.qr_parts());
+    let parsed = resolve_server_configs(parsed);
+    if parsed.nodes.is_empty() {
+        add_imported_nodes(store, title, parsed);
+    } else {
+        node_selection_dialog::show_node_selection_dialog(win, title, parsed, tx.clone());
+    }
+}
+
+/// Replace server-side configs by the client nodes connecting to them, asking for the public host when it can't be guessed
+fn resolve_server_configs(parsed: paste_operations::ParsedNodes) -> paste_operations::ParsedNodes {
+    let mut resolved = paste_operations::ParsedNodes {
+        nodes: Vec::new(),
+        rejected: parsed.rejected,
+    };
+    for node in parsed.nodes {
+        if !server_config::is_server_config(&node) {
+            resolved.nodes.push(node);
+            continue;
+        }
+        let name = node
+            .remarks
+            .clone()
+            .filter(|r| !r.is_empty())
+            .unwrap_or_else(|| "server config".to_string());
+        let host = match server_config::inferred_public_host(&node) {
+            Some(host) => Some(host),
+            None => {
+                let prompt = format!("'{name}' is an overtls server config.\nPublic host or domain clients connect to:");
+                fltk::dialog::input_default(&prompt, "")
+            }
+        };
+        let Some(host) = host.filter(|h| !h.trim().is_empty()) else {
+            resolved.rejected.push((name, "Server config, no public host given".to_string()));
+            continue;
+        };
+        match server_config::derive_client_node(&node, &host) {
+            Ok(client) => resolved.nodes.push(client),
+            Err(e) => resolved.rejected.push((name, e.to_string())),
+        }
+    }
+    resolved
+}
+
+/// Add the nodes, asking what to do with duplicates if any, and report what happened unless it's a plain single import
+fn add_imported_nodes(store: &StateStore, title: &str, parsed: paste_operations::ParsedNodes) {
+    let duplicates = store.state().count_duplicates(&parsed.nodes);
+    let policy = if duplicates == 0 {
+        DuplicatePolicy::Skip
+    } else {
+        match ask_duplicate_policy(title, duplicates) {
+            Some(policy) => policy,
+            None => return,
+        }
+    };
+    let (added, duplicates) = store.update(StateEvent::NodesChanged, |s| s.add_nodes(parsed.nodes, policy));
+    let report = paste_operations::ImportReport {
+        added,
+        duplicates,
+        policy,
+        rejected: parsed.rejected,
+    };
+    log::info!("{title}: {report}");
+    if !report.is_single_success() {
+        let level = if added == 0 {
+            rfd::MessageLevel::Warning
+        } else {
+            rfd::MessageLevel::Info
+        };
+        rfd::MessageDialog::new()
+            .set_title(title)
+            .set_description(report.to_string())
+            .set_level(level)
+            .show();
+    }
+}
+
+/// `None` when the user cancels the import.
+/// A window of its own, message boxes have no room for three choices besides Cancel.
+fn ask_duplicate_policy(title: &str, duplicates: usize) -> Option<DuplicatePolicy> {
+    use fltk::{button::Button, frame::Frame};
+
+    let dialog_w = 480;
+    let dialog_h = 180;
+    let mut dlg = Window::default().with_size(dialog_w, dialog_h).with_label(title).center_screen();
+    if let Ok(icon) = crate::util::get_embedded_main_icon() {
+        dlg.set_icon(Some(icon));
+    }
+    let mut hint = Frame::new(10, 5, dialog_w - 20, dialog_h - 60, None);
+    hint.set_label(&format!(
+        "{duplicates} imported node(s) connect to the same server as a node already in the list.\n\n\
+         Skip: keep the listed nodes and drop the duplicates.\n\
+         Replace: overwrite the listed nodes with the imported ones.\n\
+         Keep Both: add the duplicates anyway."
+    ));
+    hint.set_align(fltk::enums::Align::Left | fltk::enums::Align::Inside | fltk::enums::Align::Wrap);
+
+    let choice = std::rc::Rc::new(std::cell::Cell::new(None));
+    let buttons = [
+        ("Skip", Some(DuplicatePolicy::Skip)),
+        ("Replace", Some(DuplicatePolicy::Replace)),
+        ("Keep Both", Some(DuplicatePolicy::KeepBoth)),
+        ("Cancel", None),
+    ];
+    for (i, (label, policy)) in buttons.into_iter().enumerate() {
+        let mut btn = Button::new(10 + i as i32 * 117, dialog_h - 50, 107, 35, label);
+        let (choice, mut dlg_cb) = (choice.clone(), dlg.clone());
+        btn.set_callback(move |_| {
+            choice.set(policy);
+            dlg_cb.hide();
+        });
+    }
+    dlg.end();
+    dlg.make_modal(true);
+    dlg.show();
+    // Closing the window or pressing Esc hides it without a choice, that is a cancel
+    while dlg.shown() && fltk::app::wait() {}
+    Window::delete(dlg);
+    choice.get()
+}
+
+/// Start fetching the subscriptions of the active profile matching `filter`, their nodes get replaced when the results arrive
+fn refresh_subscriptions(
+    store: &StateStore,
+    in_flight: &SubscriptionsInFlight,
+    tx: &std::sync::mpsc::Sender<subscriptions::FetchOutcome>,
+    filter: impl Fn(&subscriptions::Subscription) -> bool,
+) {
+    let targets: Vec<(subscriptions::SubscriptionId, String)> = store
+        .state()
+        .subscriptions
+        .iter()
+        .filter(|s| !in_flight.borrow().contains(&s.id) && filter(s))
+        .map(|s| (s.id, s.url.clone()))
+        .collect();
+    if targets.is_empty() {
+        return;
+    }
+    let now = chrono::Utc::now();
+    store.update(StateEvent::SubscriptionsChanged, |s| {
+        for (id, _) in &targets {
+            if let Some(sub) = s.subscription_mut(*id) {
+                sub.last_attempt = Some(now);
+            }
+        }
+    });
+    let profile = store.state().active_profile.clone();
+    for (id, url) in targets {
+        in_flight.borrow_mut().insert(id);
+        subscriptions::spawn_fetch(profile.clone(), id, url, tx.clone());
+    }
+}
+
+/// Sync the Profile menu with the state: the "Use Own Settings" check mark and one radio item per profile
+fn rebuild_profile_menu(menubar: &mut MenuBar, store: &StateStore, switch_profile: &std::rc::Rc<dyn Fn(&str)>) {
+    let state = store.state();
+    if let Some(mut item) = menubar.find_item("&Profile/Use Own Settings") {
+        if state.profile_settings.is_some() {
+            item.set();
+        } else {
+            item.clear();
+        }
+    }
+    let active = state.active_profile.clone();
+    let mut names = state.profile_names();
+    drop(state);
+    names.sort();
+
+    let index = menubar.find_index("&Profile/Switch To");
+    if index >= 0
+        && let Err(e) = menubar.clear_submenu(index)
+    {
+        log::error!("Failed to clear profile menu: {e}");
+    }
+    for name in names {
+        let flag = if name == active {
+            MenuFlag::Radio | MenuFlag::Value
+        } else {
+            MenuFlag::Radio
+        };
+        let switch_profile = switch_profile.clone();
+        let profile = name.clone();
+        menubar.add(&format!("&Profile/Switch To/{name}"), Shortcut::None, flag, move |_| {
+            switch_profile(&profile)
+        });
+    }
+}
+
+fn prompt_profile_name(prompt: &str, default: &str) -> Option<String> {
+    fltk::dialog::input_default(prompt, default).map(|name| name.trim().to_string())
+}
+
+fn show_profile_error(e: &std::io::Error) {
+    rfd::MessageDialog::new()
+        .set_title("Profile")
+        .set_description(e.to_string())
+        .set_level(rfd::MessageLevel::Error)
+        .show();
+}
+
+/// Load the app state, and if `config.json` is unreadable, offer to restore the newest valid backup.
+/// Returns `None` if the application should not start, leaving the broken file untouched.
+fn load_app_state_or_recover() -> Option<states_manager::AppState> {
+    let e = match states_manager::load_app_state() {
+        Ok(state) => return Some(state),
+        Err(e) => e,
+    };
+    let has_backups = !states_manager::list_backups().is_empty();
+    let description = if has_backups {
+        format!("The configuration could not be loaded:\n\n{e}\n\nRestore the newest valid backup?")
+    } else {
+        format!("The configuration could not be loaded:\n\n{e}\n\nNo backup is available. Please fix or remove the file and try again.")
+    };
+    let buttons = if has_backups {
+        rfd::MessageButtons::YesNo
+    } else {
+        rfd::MessageButtons::Ok
+    };
+    let answer = rfd::MessageDialog::new()
+        .set_title("Configuration Error")
+        .set_description(description)
+        .set_buttons(buttons)
+        .set_level(rfd::MessageLevel::Error)
+        .show();
+    if answer != rfd::MessageDialogResult::Yes {
+        return None;
+    }
+    match states_manager::restore_newest_valid_backup() {
+        Ok((state, backup)) => {
+            rfd::MessageDialog::new()
+                .set_title("Backup Restored")
+                .set_description(format!("Configuration restored from {}", backup.display()))
+                .set_level(rfd::MessageLevel::Info)
+                .show();
+            Some(state)
+        }
+        Err(e) => {
+            rfd::MessageDialog::new()
+                .set_title("Error")
+                .set_description(format!("Failed to restore backup: {e}"))
+                .set_level(rfd::MessageLevel::Error)
+                .show();
+            None
+        }
+    }
+}
+
+/// Ask for the master passphrase until the nodes are decrypted.
+/// If the passphrase is forgotten, the encrypted nodes can be discarded while the settings are kept.
+/// Returns `false` if the application should not start.
+fn unlock_app_state(state: &mut states_manager::AppState) -> bool {
+    loop {
+        let Some(passphrase) = fltk::dialog::password_default("Enter the master passphrase to unlock your nodes:", "") else {
+            let answer = rfd::MessageDialog::new()
+                .set_title("Forgot Passphrase?")
+                .set_description(
+                    "Without the master passphrase the encrypted nodes can't be recovered.\n\n\
+                     Discard them and continue with your settings only? A backup of the encrypted configuration is kept.",
+                )
+                .set_buttons(rfd::MessageButtons::YesNo)
+                .set_level(rfd::MessageLevel::Warning)
+                .show();
+            if answer != rfd::MessageDialogResult::Yes {
+                return false;
+            }
+            // Still encrypted, it's kept as it is
+            if let Err(e) = states_manager::backup_config_now(None) {
+                rfd::MessageDialog::new()
+                    .set_title("Error")
+                    .set_description(format!("Failed to back up the encrypted configuration, nothing was discarded: {e}"))
+                    .set_level(rfd::MessageLevel::Error)
+                    .show();
+                return false;
+            }
+            state.discard_encrypted_nodes();
+            return true;
+        };
+        match state.unlock(&passphrase) {
+            Ok(()) => return true,
+            Err(e) => {
+                rfd::MessageDialog::new()
+                    .set_title("Error")
+                    .set_description(format!("Failed to unlock nodes: {e}"))
+                    .set_level(rfd::MessageLevel::Error)
+                    .show();
+            }
+        }
+    }
+}
+
+/// Decrypt the nodes of a backup, with the current master passphrase if it matches, else by asking for the backup's one
+fn unlock_backup_archive(store: &StateStore, archive: &mut states_manager::AppState) -> bool {
+    let vault = store.state().vault.clone();
+    if let Some(vault) = vault
+        && archive.unlock_with_vault(&vault).is_ok()
+    {
+        return true;
+    }
+    loop {
+        let Some(passphrase) = fltk::dialog::password_default("The backup is encrypted, enter its master passphrase:", "") else {
+            return false;
+        };
+        match archive.unlock(&passphrase) {
+            Ok(()) => return true,
+            Err(e) => {
+                rfd::MessageDialog::new()
+                    .set_title("Error")
+                    .set_description(format!("Failed to unlock the backup: {e}"))
+                    .set_level(rfd::MessageLevel::Error)
+                    .show();
+            }
+        }
+    }
+}
+
+/// Ask for the current master passphrase, if any, before changing or removing it
+fn verify_current_passphrase(store: &StateStore) -> bool {
+    let Some(vault) = store.state().vault.clone() else {
+        return true;
+    };
+    let Some(passphrase) = fltk::dialog::password_default("Enter the current master passphrase:", "") else {
+        return false;
+    };
+    if vault.verify(&passphrase) {
+        return true;
+    }
+    rfd::MessageDialog::new()
+        .set_title("Error")
+        .set_description("Wrong passphrase.")
+        .set_level(rfd::MessageLevel::Error)
+        .show();
+    false
+}
+
+/// Ask for a new master passphrase twice, returns `None` if cancelled or mismatched
+fn prompt_new_passphrase() -> Option<String> {
+    let passphrase = fltk::dialog::password_default("Enter the new master passphrase:", "")?;
+    if passphrase.is_empty() {
+        return None;
+    }
+    let confirmation = fltk::dialog::password_default("Confirm the new master passphrase:", "")?;
+    if passphrase != confirmation {
+        rfd::MessageDialog::new()
+            .set_title("Error")
+            .set_description("The passphrases don't match.")
+            .set_level(rfd::MessageLevel::Error)
+            .show();
+        return None;
+    }
+    Some(passphrase)
+}
@@ -37,6 +37,109 @@ macro_rules! add_row_check {
     }};
 }
 
+/// Text of the dialog's fields, so that only what the user changed is written back to the node
+#[derive(Debug, Clone, Default)]
+struct NodeForm {
+    remarks: String,
+    tunnel_path: String,
+    disable_tls: bool,
+    client_id: String,
+    server_host: String,
+    server_port: String,
+    server_domain: String,
+    cafile: String,
+    dangerous_mode: bool,
+}
+
+impl NodeForm {
+    fn from_node(node: &OverTlsNode) -> Self {
+        let mut form = NodeForm {
+            remarks: node.remarks.clone().unwrap_or_default(),
+            tunnel_path: tunnel_path_text(&node.tunnel_path),
+            ..NodeForm::default()
+        };
+        if let Some(client) = &node.client {
+            form.disable_tls = client.disable_tls.unwrap_or(false);
+            form.client_id = client.client_id.clone().unwrap_or_default();
+            form.server_host = client.server_host.clone();
+            form.server_port = client.server_port.to_string();
+            form.server_domain = client.server_domain.clone().unwrap_or_default();
+            form.cafile = client.cafile.clone().unwrap_or_default();
+            form.dangerous_mode = client.dangerous_mode.unwrap_or(false);
+        }
+        form
+    }
+
+    /// Write the fields that differ from `original` to `node`, every field when there is no original (a new node).
+    /// Whatever the dialog doesn't show is left untouched.
+    fn apply_to(&self, original: Option<&NodeForm>, node: &mut OverTlsNode) {
+        macro_rules! changed {
+            ($($field:ident),+) => {
+                original.is_none_or(|o| $(o.$field != self.$field)||+)
+            };
+        }
+        let non_empty = |s: &str| (!s.is_empty()).then(|| s.to_string());
+
+        if changed!(remarks) {
+            node.remarks = non_empty(&self.remarks);
+        }
+        if changed!(tunnel_path) {
+            node.tunnel_path = parse_tunnel_path(&self.tunnel_path);
+        }
+        // Nodes without a client side keep it that way unless a client field is edited
+        if !changed!(
+            disable_tls,
+            client_id,
+            server_host,
+            server_port,
+            server_domain,
+            cafile,
+            dangerous_mode
+        ) {
+            return;
+        }
+        let client = node.client.get_or_insert_with(ClientConfig::default);
+        if changed!(disable_tls) {
+            client.disable_tls = Some(self.disable_tls);
+        }
+        if changed!(client_id) {
+            client.client_id = non_empty(&self.client_id);
+        }
+        if changed!(server_host) {
+            client.server_host = self.server_host.clone();
+        }
+        if changed!(server_port) {
+            client.server_port = self.server_port.trim().parse().unwrap_or(443);
+        }
+        if changed!(server_domain) {
+            client.server_domain = non_empty(&self.server_domain);
+        }
+        if changed!(cafile) {
+            client.cafile = non_empty(&self.cafile);
+        }
+        if changed!(dangerous_mode) {
+            client.dangerous_mode = Some(self.dangerous_mode);
+        }
+    }
+}
+
+/// Several tunnel paths are shown separated by commas
+fn tunnel_path_text(tunnel_path: &TunnelPath) -> String {
+    match tunnel_path {
+        TunnelPath::Single(path) => path.clone(),
+        TunnelPath::Multiple(paths) => paths.join(", "),
+    }
+}
+
+fn parse_tunnel_path(text: &str) -> TunnelPath {
+    let mut paths: Vec<String> = text.split(',').map(str::trim).filter(|p| !p.is_empty()).map(String::from).collect();
+    if paths.len() > 1 {
+        TunnelPath::Multiple(paths)
+    } else {
+        TunnelPath::Single(paths.pop().unwrap_or_default())
+    }
+}
+
 pub fn show_node_details(win: &Window, node_cfg: Option<OverTlsNode>, tx: std::sync::mpsc::Sender<Option<OverTlsNode>>) {
     let dialog_w = 500;
     let dialog_h = 360;
@@ -68,18 +171,18 @@ pub fn show_node_details(win: &Window, node_cfg: Option<OverTlsNode>, tx: std::s
     let mut cafile = add_row_input!(flex, "CA File/Content", cafile);
     let mut dangerous_mode = add_row_check!(flex, "Dangerous Mode", dangerous_mode);
 
-    if let Some(cfg) = &node_cfg {
-        remarks.set_value(cfg.remarks.as_ref().map_or("", |v| v));
-        tunnel_path.set_value(cfg.tunnel_path.to_string().as_str());
-        if let Some(client) = &cfg.client {
-            disable_tls.set_value(client.disable_tls.unwrap_or(false));
-            client_id.set_value(client.client_id.as_ref().map_or("", |v| v));
-            server_host.set_value(client.server_host.as_str());
-            server_port.set_value(&client.server_port.to_string());
-            server_domain.set_value(client.server_domain.as_ref().map_or("", |v| v));
-            cafile.set_value(client.cafile.as_ref().map_or("", |v| v));
-            dangerous_mode.set_value(client.dangerous_mode.unwrap_or(false));
-        }
+    // Edits are applied to the original node, keeping the fields the dialog doesn't show
+    let original_form = node_cfg.as_ref().map(NodeForm::from_node);
+    if let Some(form) = &original_form {
+        remarks.set_value(&form.remarks);
+        tunnel_path.set_value(&form.tunnel_path);
+        disable_tls.set_value(form.disable_tls);
+        client_id.set_value(&form.client_id);
+        server_host.set_value(&form.server_host);
+        server_port.set_value(&form.server_port);
+        server_domain.set_value(&form.server_domain);
+        cafile.set_value(&form.cafile);
+        dangerous_mode.set_value(form.dangerous_mode);
     }
 
     let mut submit_btn = Button::default().with_label("Submit");
@@ -91,30 +194,19 @@ pub fn show_node_details(win: &Window, node_cfg: Option<OverTlsNode>, tx: std::s
     let mut dlg_cb = dlg.clone();
     let tx_cb = tx.clone();
     submit_btn.set_callback(move |_b| {
-        let mut client = ClientConfig::default();
-
-        client.disable_tls = Some(disable_tls.value());
-        client.client_id = if client_id.value().is_empty() {
-            None
-        } else {
-            Some(client_id.value())
-        };
-        client.server_host = server_host.value();
-        client.server_port = server_port.value().parse().unwrap_or(443);
-        client.server_domain = if server_domain.value().is_empty() {
-            None
-        } else {
-            Some(server_domain.value())
-        };
-        client.cafile = if cafile.value().is_empty() { None } else { Some(cafile.value()) };
-        client.dangerous_mode = Some(dangerous_mode.value());
-
-        let config = OverTlsNode {
-            remarks: if remarks.value().is_empty() { None } else { Some(remarks.value()) },
-            tunnel_path: TunnelPath::Single(tunnel_path.value()),
-            client: Some(client),
-            ..OverTlsNode::default()
+        let form = NodeForm {
+            remarks: remarks.value(),
+            tunnel_path: tunnel_path.value(),
+            disable_tls: disable_tls.value(),
+            client_id: client_id.value(),
+            server_host: server_host.value(),
+            server_port: server_port.value(),
+            server_domain: server_domain.value(),
+            cafile: cafile.value(),
+            dangerous_mode: dangerous_mode.value(),
         };
+        let mut config = node_cfg.clone().unwrap_or_default();
+        form.apply_to(original_form.as_ref(), &mut config);
         let _ = tx_cb.send(Some(config));
         dlg_cb.hide();
     });
@@ -139,3 +231,80 @@ pub fn show_node_details(win: &Window, node_cfg: Option<OverTlsNode>, tx: std::s
         dlg_close.hide();
     });
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture_node(tunnel_path: TunnelPath) -> OverTlsNode {
+        let mut node = crate::states_manager::test_fixtures::fixture_node();
+        node.tunnel_path = tunnel_path;
+        let client = node.client.as_mut().unwrap();
+        client.cafile = Some("-----BEGIN CERTIFICATE-----\nMIIB\n-----END CERTIFICATE-----\n".into());
+        client.dangerous_mode = Some(true);
+        node
+    }
+
+    /// The form read back from the widgets on submit, built from what the user sees rather than from `NodeForm::from_node`
+    fn submitted_form(node: &OverTlsNode, tunnel_path_text: &str) -> NodeForm {
+        let client = node.client.as_ref().unwrap();
+        NodeForm {
+            remarks: node.remarks.clone().unwrap_or_default(),
+            tunnel_path: tunnel_path_text.to_string(),
+            disable_tls: client.disable_tls == Some(true),
+            client_id: client.client_id.clone().unwrap_or_default(),
+            server_host: client.server_host.clone(),
+            server_port: client.server_port.to_string(),
+            server_domain: client.server_domain.clone().unwrap_or_default(),
+            cafile: client.cafile.clone().unwrap_or_default(),
+            dangerous_mode: client.dangerous_mode == Some(true),
+        }
+    }
+
+    fn submit(node: &OverTlsNode, form: &NodeForm) -> OverTlsNode {
+        let mut submitted = node.clone();
+        form.apply_to(Some(&NodeForm::from_node(node)), &mut submitted);
+        submitted
+    }
+
+    #[test]
+    fn unchanged_nodes_round_trip() {
+        let single = fixture_node(TunnelPath::Single("/secret-tunnel-path/".into()));
+        let multiple = fixture_node(TunnelPath::Multiple(vec!["/path-a/".into(), "/path-b/".into()]));
+        for (node, text) in [(single, "/secret-tunnel-path/"), (multiple, "/path-a/, /path-b/")] {
+            let submitted = submit(&node, &submitted_form(&node, text));
+            assert_eq!(serde_json::to_value(&submitted).unwrap(), serde_json::to_value(&node).unwrap());
+        }
+    }
+
+    #[test]
+    fn editing_remarks_keeps_other_fields() {
+        let node = fixture_node(TunnelPath::Multiple(vec!["/path-a/".into(), "/path-b/".into()]));
+        let form = NodeForm {
+            remarks: "Renamed".to_string(),
+            ..submitted_form(&node, "/path-a/, /path-b/")
+        };
+        let edited = submit(&node, &form);
+
+        assert_eq!(edited.remarks.as_deref(), Some("Renamed"));
+        let client = edited.client.as_ref().unwrap();
+        assert_eq!(client.cafile, node.client.as_ref().unwrap().cafile);
+        assert_eq!(client.dangerous_mode, Some(true));
+        assert_eq!(
+            serde_json::to_value(&edited.tunnel_path).unwrap(),
+            serde_json::json!(["/path-a/", "/path-b/"])
+        );
+        let mut expected = serde_json::to_value(&node).unwrap();
+        expected["remarks"] = serde_json::json!("Renamed");
+        assert_eq!(serde_json::to_value(&edited).unwrap(), expected);
+    }
+
+    #[test]
+    fn edited_tunnel_paths_are_split_on_commas() {
+        let paths = |text: &str| serde_json::to_value(parse_tunnel_path(text)).unwrap();
+        assert_eq!(paths(" /a/ "), serde_json::json!("/a/"));
+        assert_eq!(paths("/a/, /b/"), serde_json::json!(["/a/", "/b/"]));
+        let node = fixture_node(TunnelPath::Multiple(vec!["/a/".into(), "/b/".into()]));
+        assert_eq!(NodeForm::from_node(&node).tunnel_path, "/a/, /b/");
+    }
+}
@@ -0,0 +1,89 @@
+use crate::paste_operations::ParsedNodes;
+use fltk::{
+    browser::CheckBrowser,
+    button::Button,
+    frame::Frame,
+    prelude::{GroupExt, WidgetBase, WidgetExt, WindowExt},
+    window::Window,
+};
+
+/// Let the user pick which of several found nodes to import, all checked by default.
+/// The picked nodes are sent via channel, together with the entries that were already rejected, nothing on cancel.
+pub fn show_node_selection_dialog(win: &Window, title: &str, parsed: ParsedNodes, tx: std::sync::mpsc::Sender<ParsedNodes>) {
+    let dialog_w = 560;
+    let dialog_h = 400;
+    let x = win.x() + (win.width() - dialog_w) / 2;
+    let y = win.y() + (win.height() - dialog_h) / 2;
+    let mut dlg = Window::new(x, y, dialog_w, dialog_h, title);
+    if let Ok(icon) = crate::util::get_embedded_main_icon() {
+        dlg.set_icon(Some(icon));
+    }
+
+    let mut hint = Frame::new(10, 5, dialog_w - 20, 30, None);
+    let mut label = format!("{} node(s) found, uncheck the ones not to import:", parsed.nodes.len());
+    if !parsed.rejected.is_empty() {
+        label = format!(
+            "{} ({} unreadable entries are reported after the import)",
+            label.trim_end_matches(':'),
+            parsed.rejected.len()
+        );
+    }
+    hint.set_label(&label);
+    hint.set_align(fltk::enums::Align::Left | fltk::enums::Align::Inside);
+
+    let mut list = CheckBrowser::new(10, 40, dialog_w - 20, dialog_h - 100, None);
+    for node in &parsed.nodes {
+        list.add(&node_summary(node), true);
+    }
+
+    let mut select_all_btn = Button::new(10, dialog_h - 50, 110, 35, "Select All");
+    let mut select_none_btn = Button::new(130, dialog_h - 50, 110, 35, "Select None");
+    let mut import_btn = Button::new(dialog_w - 250, dialog_h - 50, 110, 35, "Import");
+    let mut cancel_btn = Button::new(dialog_w - 120, dialog_h - 50, 110, 35, "Cancel");
+    dlg.end();
+    dlg.show();
+
+    let mut list_cb = list.clone();
+    select_all_btn.set_callback(move |_| list_cb.check_all());
+    let mut list_cb = list.clone();
+    select_none_btn.set_callback(move |_| list_cb.check_none());
+
+    let mut dlg_cb = dlg.clone();
+    cancel_btn.set_callback(move |_| dlg_cb.hide());
+
+    let mut dlg_cb = dlg.clone();
+    let mut parsed = Some(parsed);
+    import_btn.set_callback(move |_| {
+        let Some(parsed) = parsed.take() else {
+            return;
+        };
+        // CheckBrowser items are 1-based
+        let nodes = parsed
+            .nodes
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| list.checked(*i as i32 + 1))
+            .map(|(_, node)| node)
+            .collect();
+        let _ = tx.send(ParsedNodes {
+            nodes,
+            rejected: parsed.rejected,
+        });
+        dlg_cb.hide();
+    });
+}
+
+/// One line describing a node: its remarks and where it connects to
+pub fn node_summary(node: &crate::OverTlsNode) -> String {
+    let remarks = node.remarks.clone().unwrap_or_default();
+    let server = node
+        .client
+        .as_ref()
+        .map(|c| format!("{}:{}", c.server_host, c.server_port))
+        .unwrap_or_default();
+    if remarks.is_empty() {
+        server
+    } else {
+        format!("{remarks} ({server})")
+    }
+}
@@ -1,59 +1,365 @@
-use crate::OverTlsNode;
+use crate::{
+    OverTlsNode,
+    qr_parts::{PART_PREFIX, PartAssembler},
+    share_link::{SHARE_LINK_SCHEMES, parse_share_link},
+};
 
-pub fn paste() -> std::io::Result<OverTlsNode> {
+/// Nodes parsed from a text holding one or many configs, along with the entries that failed and why
+#[derive(Default)]
+pub struct ParsedNodes {
+    pub nodes: Vec<OverTlsNode>,
+    pub rejected: Vec<(String, String)>,
+}
+
+impl ParsedNodes {
+    pub fn extend(&mut self, other: ParsedNodes) {
+        self.nodes.extend(other.nodes);
+        self.rejected.extend(other.rejected);
+    }
+}
+
+/// Outcome of importing `ParsedNodes` into the node list
+pub struct ImportReport {
+    pub added: usize,
+    pub duplicates: usize,
+    pub policy: crate::states_manager::DuplicatePolicy,
+    pub rejected: Vec<(String, String)>,
+}
+
+impl ImportReport {
+    /// Nothing surprising happened, no need to bother the user with a report
+    pub fn is_single_success(&self) -> bool {
+        self.added == 1 && self.duplicates == 0 && self.rejected.is_empty()
+    }
+}
+
+impl std::fmt::Display for ImportReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use crate::states_manager::DuplicatePolicy;
+        const MAX_LISTED: usize = 20;
+        let handled = match self.policy {
+            DuplicatePolicy::Skip => "skipped",
+            DuplicatePolicy::Replace => "replaced",
+            DuplicatePolicy::KeepBoth => "also added",
+        };
+        write!(
+            f,
+            "Added {} node(s), {handled} {} duplicate(s), rejected {}.",
+            self.added,
+            self.duplicates,
+            self.rejected.len()
+        )?;
+        for (entry, reason) in self.rejected.iter().take(MAX_LISTED) {
+            write!(f, "\n- {entry}: {reason}")?;
+        }
+        if self.rejected.len() > MAX_LISTED {
+            write!(f, "\n- ... and {} more", self.rejected.len() - MAX_LISTED)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parse text holding a JSON config, or any number of `overtls://` or `ssr://` links separated by whitespace,
+/// possibly base64 encoded as a whole like a subscription. Text around the links, e.g. from a chat, is ignored.
+pub fn parse_links_text(text: &str) -> ParsedNodes {
+    let mut parsed = ParsedNodes::default();
+    let text = text.trim();
+    if text.is_empty() {
+        parsed.rejected.push((String::new(), "Empty text".to_string()));
+        return parsed;
+    }
+    if let Ok(node) = OverTlsNode::from_json_str(text) {
+        parsed.nodes.push(node);
+        return parsed;
+    }
+    let has_links = |text: &str| SHARE_LINK_SCHEMES.iter().any(|scheme| text.contains(scheme));
+    let decoded;
+    let text = if has_links(text) {
+        text
+    } else if let Some(d) = crate::subscriptions::decode_base64_text(text).filter(|d| has_links(d)) {
+        decoded = d;
+        &decoded
+    } else {
+        parsed
+            .rejected
+            .push((abbreviate(text), "No share link or JSON config found".to_string()));
+        return parsed;
+    };
+    for token in text.split_whitespace() {
+        let Some(start) = SHARE_LINK_SCHEMES.iter().filter_map(|scheme| token.find(scheme)).min() else {
+            continue;
+        };
+        // Strip the punctuation links get wrapped in when pasted in prose
+        let link = token[start..].trim_end_matches([',', ';', '"', '\'', ')', ']', '>', '`']);
+        match parse_share_link(link) {
+            Ok(node) => parsed.nodes.push(node),
+            Err(e) => parsed.rejected.push((abbreviate(link), e.to_string())),
+        }
+    }
+    parsed
+}
+
+fn abbreviate(text: &str) -> String {
+    const MAX_CHARS: usize = 40;
+    if text.chars().count() <= MAX_CHARS {
+        return text.to_string();
+    }
+    let head: String = text.chars().take(MAX_CHARS).collect();
+    format!("{head}...")
+}
+
+pub fn paste(parts: &mut PartAssembler) -> std::io::Result<ParsedNodes> {
     // Use arboard::Clipboard for cross-platform clipboard access
     let mut clipboard = arboard::Clipboard::new().map_err(|e| std::io::Error::other(format!("Clipboard error: {e}")))?;
 
     // Try to get text from clipboard
     if let Ok(text) = clipboard.get_text() {
         log::trace!("Pasted text: {text}");
-        // Try to parse the text as a config
-        return OverTlsNode::from_json_str(&text)
-            .or_else(|_| OverTlsNode::from_ssr_url(&text))
-            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Some unknown error occurred: {e}")));
+        return Ok(parse_links_text(&text));
     }
 
     // Try to get image from clipboard (requires arboard image-data feature)
     let Ok(img) = clipboard.get_image() else {
         return Err(std::io::Error::other("Another paste operations not implemented"));
     };
+    clipboard_image_nodes(img, parts)
+}
+
+/// Nodes from the QR codes in an image taken from the clipboard
+pub fn clipboard_image_nodes(img: arboard::ImageData, parts: &mut PartAssembler) -> std::io::Result<ParsedNodes> {
     // Convert arboard::ImageData to image::DynamicImage
     let dyn_img = image::DynamicImage::ImageRgba8(
         image::RgbaImage::from_raw(img.width as u32, img.height as u32, img.bytes.into_owned())
             .ok_or_else(|| std::io::Error::other("Failed to convert clipboard image"))?,
     );
-    config_from_image(&dyn_img)
+    nodes_from_image(&dyn_img, parts).map(|parsed| with_missing_parts(parsed, parts))
 }
 
-pub fn process_inputed_file<P: AsRef<std::path::Path>>(path: P) -> std::io::Result<OverTlsNode> {
+/// Extensions of the files `parse_files` understands, for the file chooser filter
+pub const IMPORT_FILE_EXTENSIONS: &[&str] = &["json", "txt", "png", "jpg", "jpeg"];
+
+/// Parse node files of any supported kind: JSON configs, QR code images and text files of share links.
+/// Files that can't be used end up in `rejected` with the reason, parts of split QR codes are collected in `parts`.
+pub fn parse_files<P: AsRef<std::path::Path>>(paths: &[P], parts: &mut PartAssembler) -> ParsedNodes {
+    let mut parsed = ParsedNodes::default();
+    for path in paths {
+        let path = path.as_ref();
+        match process_inputed_file(path, parts) {
+            Ok(file_parsed) => parsed.extend(file_parsed),
+            Err(e) => {
+                log::warn!("Failed to load file: {e}");
+                parsed.rejected.push((path.display().to_string(), e.to_string()));
+            }
+        }
+    }
+    with_missing_parts(parsed, parts)
+}
+
+/// Parse what was given on the command line: share links and paths of node files, see `cli::CliArgs::imports`
+pub fn parse_import_args(items: &[String], parts: &mut PartAssembler) -> ParsedNodes {
+    let (links, paths): (Vec<&String>, Vec<&String>) = items
+        .iter()
+        .partition(|item| SHARE_LINK_SCHEMES.iter().any(|scheme| item.starts_with(scheme)));
+    let mut parsed = ParsedNodes::default();
+    if !links.is_empty() {
+        let text = links.iter().map(|link| link.as_str()).collect::<Vec<_>>().join("\n");
+        parsed.extend(parse_links_text(&text));
+    }
+    if !paths.is_empty() {
+        parsed.extend(parse_files(&paths, parts));
+    }
+    parsed
+}
+
+pub fn process_inputed_file<P: AsRef<std::path::Path>>(path: P, parts: &mut PartAssembler) -> std::io::Result<ParsedNodes> {
     use std::io::{Error, ErrorKind::InvalidData};
     let path = path.as_ref();
-    // 1. try to parse as config file
+    let single = |config| ParsedNodes {
+        nodes: vec![config],
+        rejected: Vec::new(),
+    };
+    let extension = path.extension().and_then(|e| e.to_str()).map(str::to_ascii_lowercase);
+    match extension.as_deref() {
+        Some("json") => {
+            return OverTlsNode::from_config_file(path)
+                .map(single)
+                .map_err(|e| Error::new(InvalidData, format!("Config file {path:?}: {e}")));
+        }
+        Some("txt") => {
+            let parsed = parse_links_text(&std::fs::read_to_string(path)?);
+            if parsed.nodes.is_empty() {
+                return Err(Error::new(InvalidData, format!("No share link or JSON config found in {path:?}")));
+            }
+            return Ok(parsed);
+        }
+        _ => {}
+    }
+    // Other names, e.g. dropped files without extension: try a config, then links, then an image
     if let Ok(config) = OverTlsNode::from_config_file(path) {
-        return Ok(config);
+        return Ok(single(config));
+    }
+    if let Ok(text) = std::fs::read_to_string(path) {
+        let parsed = parse_links_text(&text);
+        if !parsed.nodes.is_empty() {
+            return Ok(parsed);
+        }
     }
-    // 2. try to parse as image
     let img = image::open(path).map_err(|e| Error::new(InvalidData, format!("Failed to load file {path:?} as image: {e}")))?;
 
-    // 3. try to parse QR code
-    let qr_str = qr_decode(&img).map_err(|e| Error::new(InvalidData, format!("Failed to decode QR code from image {path:?}: {e}")))?;
+    // Parse the QR codes
+    nodes_from_image(&img, parts).map_err(|e| Error::new(InvalidData, format!("Image {path:?}: {e}")))
+}
+
+/// Scan every attached display for QR codes
+pub fn screenshot_qr_import(parts: &mut PartAssembler) -> std::io::Result<ParsedNodes> {
+    // Wayland compositors don't let clients grab the screen, only the portal can
+    #[cfg(target_os = "linux")]
+    if is_wayland_session() {
+        match portal_screenshot(false) {
+            Ok(img) => return nodes_from_image(&img, parts).map(|parsed| with_missing_parts(parsed, parts)),
+            Err(e) => log::warn!("Portal screenshot failed, falling back to direct capture: {e}"),
+        }
+    }
+
+    let screen_count = fltk::app::screen_count().max(1) as usize;
+    let mut parsed = ParsedNodes::default();
+    let mut found = false;
+    let mut last_error = None;
+    for display in 0..screen_count {
+        let result = screenshot_to_image(display).and_then(|img| nodes_from_image(&img, parts));
+        match result {
+            Ok(display_parsed) => {
+                found = true;
+                parsed.extend(display_parsed);
+            }
+            Err(e) => {
+                log::debug!("Display {display}: {e}");
+                last_error = Some(e);
+            }
+        }
+    }
+    match last_error {
+        Some(e) if !found => Err(std::io::Error::new(
+            e.kind(),
+            format!("No QR code found on {screen_count} display(s): {e}"),
+        )),
+        _ => Ok(with_missing_parts(parsed, parts)),
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) fn is_wayland_session() -> bool {
+    std::env::var_os("WAYLAND_DISPLAY").is_some_and(|v| !v.is_empty())
+        || std::env::var("XDG_SESSION_TYPE").is_ok_and(|v| v.eq_ignore_ascii_case("wayland"))
+}
+
+/// Capture the desktop through the xdg-desktop-portal Screenshot interface.
+/// `interactive` lets the user pick the area in the compositor's own dialog, backing out of it is an `Interrupted` error.
+#[cfg(target_os = "linux")]
+pub(crate) fn portal_screenshot(interactive: bool) -> std::io::Result<image::DynamicImage> {
+    async fn request_screenshot(interactive: bool) -> ashpd::Result<ashpd::desktop::screenshot::Screenshot> {
+        ashpd::desktop::screenshot::Screenshot::request()
+            .interactive(interactive)
+            .modal(true)
+            .send()
+            .await?
+            .response()
+    }
 
-    log::trace!("QR code detected: {qr_str}");
-    // 4. try to convert QR code string to config
-    let config = OverTlsNode::from_ssr_url(&qr_str).map_err(|e| Error::new(InvalidData, format!("Failed parse '{qr_str}': {e}")))?;
+    // Called from the UI thread, which already runs inside the tokio runtime of `main`
+    let response = std::thread::spawn(move || {
+        let rt = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+        rt.block_on(request_screenshot(interactive)).map_err(|e| match e {
+            ashpd::Error::Response(ashpd::desktop::ResponseError::Cancelled) => {
+                std::io::Error::new(std::io::ErrorKind::Interrupted, "Screenshot cancelled")
+            }
+            e => std::io::Error::other(format!("Screenshot portal error: {e}")),
+        })
+    })
+    .join()
+    .map_err(|_| std::io::Error::other("Screenshot portal thread panicked"))??;
+
+    let path = response
+        .uri()
+        .to_file_path()
+        .map_err(|_| std::io::Error::other(format!("Unexpected screenshot location {}", response.uri())))?;
+    let img = image::open(&path).map_err(|e| std::io::Error::other(format!("Failed to load screenshot {}: {e}", path.display())));
+    // The portal saved the screenshot only for us to scan it
+    if let Err(e) = std::fs::remove_file(&path) {
+        log::debug!("Failed to remove portal screenshot {}: {e}", path.display());
+    }
+    img
+}
 
-    Ok(config)
+/// Decode the QR codes in a region the user selected on screen, see `region_capture`.
+/// The region is enhanced first, falling back to the raw pixels if that finds nothing.
+pub fn region_qr_import(region: &image::DynamicImage, parts: &mut PartAssembler) -> std::io::Result<ParsedNodes> {
+    let enhanced = enhance_for_qr(region);
+    let result = match nodes_from_image(&enhanced, parts) {
+        Ok(parsed) if !parsed.nodes.is_empty() => Ok(parsed),
+        enhanced_result => nodes_from_image(region, parts).or(enhanced_result),
+    };
+    result.map(|parsed| with_missing_parts(parsed, parts))
+}
+
+/// Smallest side a region gets upscaled to, small codes have modules of one or two pixels that `rqrr` can't sample
+const MIN_REGION_SIDE: u32 = 600;
+
+/// Upscale a small region and binarize it with Otsu's threshold, blurry and low-contrast codes decode far better that way
+fn enhance_for_qr(img: &image::DynamicImage) -> image::DynamicImage {
+    let luma = img.to_luma8();
+    let (w, h) = luma.dimensions();
+    let factor = MIN_REGION_SIDE.div_ceil(w.min(h).max(1)).clamp(1, 8);
+    let mut upscaled = if factor > 1 {
+        image::imageops::resize(&luma, w * factor, h * factor, image::imageops::FilterType::CatmullRom)
+    } else {
+        luma
+    };
+    let threshold = otsu_threshold(&upscaled);
+    for pixel in upscaled.pixels_mut() {
+        pixel.0[0] = if pixel.0[0] > threshold { 255 } else { 0 };
+    }
+    image::DynamicImage::ImageLuma8(upscaled)
 }
 
-pub fn screenshot_qr_import() -> std::io::Result<OverTlsNode> {
-    let img = screenshot_to_image()?;
-    let scr_str = qr_decode(&img)?;
-    Ok(OverTlsNode::from_ssr_url(&scr_str)?)
+/// The gray level best separating dark and light pixels, maximizing the between-class variance
+fn otsu_threshold(img: &image::GrayImage) -> u8 {
+    let mut histogram = [0u64; 256];
+    for pixel in img.pixels() {
+        histogram[pixel.0[0] as usize] += 1;
+    }
+    let total = img.pixels().len() as f64;
+    let sum_all: f64 = histogram
+        .iter()
+        .enumerate()
+        .map(|(level, &count)| level as f64 * count as f64)
+        .sum();
+    let (mut weight_dark, mut sum_dark) = (0.0, 0.0);
+    let (mut best_level, mut best_variance) = (0u8, 0.0);
+    for (level, &count) in histogram.iter().enumerate() {
+        weight_dark += count as f64;
+        if weight_dark == 0.0 {
+            continue;
+        }
+        let weight_light = total - weight_dark;
+        if weight_light == 0.0 {
+            break;
+        }
+        sum_dark += level as f64 * count as f64;
+        let mean_dark = sum_dark / weight_dark;
+        let mean_light = (sum_all - sum_dark) / weight_light;
+        let variance = weight_dark * weight_light * (mean_dark - mean_light).powi(2);
+        if variance > best_variance {
+            best_variance = variance;
+            best_level = level as u8;
+        }
+    }
+    best_level
 }
 
-fn screenshot_to_image() -> std::io::Result<image::DynamicImage> {
-    // Take screenshot of the primary display
-    let img = screenshot::get_screenshot(0).map_err(|e| std::io::Error::other(format!("Screenshot failed: {e}")))?;
+/// Capture one display, in physical pixels
+pub fn screenshot_to_image(display: usize) -> std::io::Result<image::DynamicImage> {
+    let img = screenshot::get_screenshot(display).map_err(|e| std::io::Error::other(format!("Screenshot failed: {e}")))?;
 
     // Screenshot struct: data: Vec<u8>, height, width, row_len, pixel_width
     // ARGB format, need to convert to RGBA for image crate
@@ -78,29 +384,220 @@ fn screenshot_to_image() -> std::io::Result<image::DynamicImage> {
     Ok(dyn_img)
 }
 
-fn qr_decode(img: &image::DynamicImage) -> std::io::Result<String> {
+/// Decode every QR code found in the image, each one as `Ok(content)` or the reason it failed
+fn qr_decode(img: &image::DynamicImage) -> std::io::Result<Vec<Result<String, String>>> {
     use std::io::{Error, ErrorKind::InvalidData};
     let img = img.to_luma8();
     // Prepare for detection
     let mut img = rqrr::PreparedImage::prepare(img);
     // Search for grids, without decoding
     let grids = img.detect_grids();
-    // Decode the grid
-    let (meta, content) = grids
-        .first()
-        .ok_or_else(|| Error::new(InvalidData, "Failed to get QR code grid"))?
-        .decode()
-        .map_err(|e| Error::new(InvalidData, format!("Failed to decode QR code: {e}")))?;
-    log::trace!("QR code meta: {:?}", meta);
-    Ok(content)
+    if grids.is_empty() {
+        return Err(Error::new(InvalidData, "No QR code found"));
+    }
+    // Decode each grid, a damaged one must not prevent decoding the others
+    let contents = grids
+        .iter()
+        .map(|grid| {
+            let (meta, content) = grid.decode().map_err(|e| format!("Failed to decode QR code: {e}"))?;
+            log::trace!("QR code meta: {:?}", meta);
+            Ok(content)
+        })
+        .collect();
+    Ok(contents)
 }
 
-fn config_from_image(dyn_img: &image::DynamicImage) -> std::io::Result<OverTlsNode> {
-    use std::io::{Error, ErrorKind::InvalidData};
+/// Tell the user which parts of split QR codes are still to be scanned, forgetting the sequences gone stale
+fn with_missing_parts(mut parsed: ParsedNodes, parts: &mut PartAssembler) -> ParsedNodes {
+    parts.expire(crate::qr_parts::PENDING_TTL);
+    for missing in parts.missing() {
+        parsed
+            .rejected
+            .push(("Split QR code".to_string(), format!("Scan the remaining part(s) {missing}")));
+    }
+    parsed
+}
+
+/// Nodes from every QR code in the image, an error only if no QR code is found at all.
+/// Parts of a split QR code are stored in `parts` until the last one turns up, then give the node.
+fn nodes_from_image(dyn_img: &image::DynamicImage, parts: &mut PartAssembler) -> std::io::Result<ParsedNodes> {
+    let mut parsed = ParsedNodes::default();
+    for (i, content) in qr_decode(dyn_img)?.into_iter().enumerate() {
+        let entry = format!("QR code #{}", i + 1);
+        match content {
+            Ok(qr_str) => {
+                log::trace!("QR code detected: {qr_str}");
+                let link = if qr_str.starts_with(PART_PREFIX) {
+                    match parts.add(&qr_str) {
+                        Ok(Some(link)) => link,
+                        Ok(None) => continue,
+                        Err(reason) => {
+                            parsed.rejected.push((entry, reason));
+                            continue;
+                        }
+                    }
+                } else {
+                    qr_str
+                };
+                match parse_share_link(&link) {
+                    Ok(node) => parsed.nodes.push(node),
+                    Err(e) => parsed.rejected.push((entry, format!("Failed parse '{}': {e}", abbreviate(&link)))),
+                }
+            }
+            Err(reason) => parsed.rejected.push((entry, reason)),
+        }
+    }
+    Ok(parsed)
+}
 
-    // QR parsing
-    let qr_str = qr_decode(dyn_img).map_err(|e| Error::new(InvalidData, format!("Failed to decode QR code: {e}")))?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::Engine;
 
-    // convert to overtls config
-    OverTlsNode::from_ssr_url(&qr_str).map_err(|e| Error::new(InvalidData, format!("Failed parse '{qr_str}': {e}")))
+    fn sample_links() -> Vec<String> {
+        let node = crate::states_manager::test_fixtures::fixture_node();
+        (0..3)
+            .map(|i| {
+                let mut node = node.clone();
+                node.remarks = Some(format!("Node {i}"));
+                node.generate_ssr_url().unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parse_links_from_chat_text() {
+        let links = sample_links();
+        let text = format!(
+            "Here you go:\n1. {}\n2. \"{}\", {}\nand a broken one ssr://not-valid",
+            links[0], links[1], links[2]
+        );
+        let parsed = parse_links_text(&text);
+        let remarks: Vec<_> = parsed.nodes.iter().map(|n| n.remarks.clone().unwrap()).collect();
+        assert_eq!(remarks, ["Node 0", "Node 1", "Node 2"]);
+        assert_eq!(parsed.rejected.len(), 1);
+        assert_eq!(parsed.rejected[0].0, "ssr://not-valid");
+    }
+
+    #[test]
+    fn parse_overtls_and_ssr_links_together() {
+        let links = sample_links();
+        let node = parse_share_link(&links[1]).unwrap();
+        let overtls = crate::share_link::generate_share_link(&node, crate::share_link::ShareLinkFormat::OverTlsCompressed).unwrap();
+        let parsed = parse_links_text(&format!("{}\n{overtls}", links[0]));
+        let remarks: Vec<_> = parsed.nodes.iter().map(|n| n.remarks.clone().unwrap()).collect();
+        assert_eq!(remarks, ["Node 0", "Node 1"]);
+        assert!(parsed.rejected.is_empty());
+    }
+
+    #[test]
+    fn parse_base64_wrapped_links() {
+        let links = sample_links();
+        let encoded = base64::engine::general_purpose::STANDARD.encode(links.join("\n"));
+        let parsed = parse_links_text(&encoded);
+        assert_eq!(parsed.nodes.len(), 3);
+        assert!(parsed.rejected.is_empty());
+    }
+
+    #[test]
+    fn otsu_threshold_separates_two_levels() {
+        let img = image::GrayImage::from_fn(10, 10, |x, _| image::Luma([if x < 4 { 40 } else { 200 }]));
+        let threshold = otsu_threshold(&img);
+        assert!((40..200).contains(&threshold));
+
+        let enhanced = enhance_for_qr(&image::DynamicImage::ImageLuma8(img)).to_luma8();
+        // Upscaling is capped at 8x
+        assert_eq!(enhanced.width(), 80);
+        assert!(enhanced.pixels().all(|p| p.0[0] == 0 || p.0[0] == 255));
+    }
+
+    #[test]
+    fn parse_files_of_mixed_kinds() {
+        let dir = std::env::temp_dir().join(format!("overtls-gui-import-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let links = dir.join("links.txt");
+        std::fs::write(&links, format!("# exported nodes\n{}\n", sample_links().join("\n"))).unwrap();
+        let json = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/server_config.json");
+        let empty = dir.join("empty.txt");
+        std::fs::write(&empty, "nothing here").unwrap();
+
+        let parsed = parse_files(
+            &[links, json, empty.clone(), dir.join("missing.png")],
+            &mut PartAssembler::default(),
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(parsed.nodes.len(), 4);
+        assert_eq!(parsed.rejected.len(), 2);
+        assert_eq!(parsed.rejected[0].0, empty.display().to_string());
+    }
+
+    #[test]
+    fn reassemble_split_qr_codes_from_several_images() {
+        let node = parse_share_link(&sample_links()[0]).unwrap();
+        let mut big = node.clone();
+        big.client.as_mut().unwrap().cafile = Some(format!(
+            "-----BEGIN CERTIFICATE-----\n{}\n-----END CERTIFICATE-----",
+            "A".repeat(2000)
+        ));
+        let link = crate::share_link::generate_share_link(&big, crate::share_link::ShareLinkFormat::OverTls).unwrap();
+        let parts = crate::qr_parts::split_for_qr(&link);
+        assert!(parts.len() > 1);
+
+        let images: Vec<_> = parts
+            .iter()
+            .map(|part| {
+                let code = qrcode::QrCode::new(part.as_bytes()).unwrap();
+                image::DynamicImage::ImageLuma8(code.render::<image::Luma<u8>>().build())
+            })
+            .collect();
+        let mut parts = PartAssembler::default();
+        for img in &images[..images.len() - 1] {
+            let parsed = nodes_from_image(img, &mut parts).unwrap();
+            assert!(parsed.nodes.is_empty() && parsed.rejected.is_empty());
+        }
+        let parsed = nodes_from_image(images.last().unwrap(), &mut parts).unwrap();
+        assert_eq!(parsed.nodes.len(), 1);
+        assert_eq!(serde_json::to_value(&parsed.nodes[0]).unwrap(), serde_json::to_value(&big).unwrap());
+    }
+
+    #[test]
+    fn decode_every_qr_code_of_an_image() {
+        let links = sample_links();
+        let texts = [links[0].as_str(), "not a node", links[1].as_str()];
+        let codes: Vec<_> = texts
+            .iter()
+            .map(|text| qrcode::QrCode::new(text.as_bytes()).unwrap().render::<image::Luma<u8>>().build())
+            .collect();
+        let width = codes.iter().map(|code| code.width()).sum();
+        let height = codes.iter().map(|code| code.height()).max().unwrap();
+        let mut img = image::GrayImage::from_pixel(width, height, image::Luma([255]));
+        let mut x = 0;
+        for code in &codes {
+            image::imageops::replace(&mut img, code, x, 0);
+            x += code.width() as i64;
+        }
+
+        let parsed = nodes_from_image(&image::DynamicImage::ImageLuma8(img), &mut PartAssembler::default()).unwrap();
+        let mut remarks: Vec<_> = parsed.nodes.iter().map(|n| n.remarks.clone().unwrap()).collect();
+        remarks.sort();
+        assert_eq!(remarks, ["Node 0", "Node 1"]);
+        assert_eq!(parsed.rejected.len(), 1);
+        assert!(parsed.rejected[0].1.contains("not a node"));
+    }
+
+    #[test]
+    fn image_without_qr_code_is_an_error() {
+        let img = image::DynamicImage::ImageLuma8(image::GrayImage::from_pixel(64, 64, image::Luma([255])));
+        let err = nodes_from_image(&img, &mut PartAssembler::default()).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "No QR code found");
+    }
+
+    #[test]
+    fn parse_rejects_unrelated_text() {
+        let parsed = parse_links_text("hello world");
+        assert!(parsed.nodes.is_empty());
+        assert_eq!(parsed.rejected.len(), 1);
+    }
 }
@@ -1,33 +1,235 @@
-use fltk::{frame::Frame, image::PngImage, prelude::*, window::Window};
-use image::Luma;
-use qrcode::QrCode;
-
-pub fn qr_code_dialog(parent: &Window, title: &str, ssr_url: &str) -> std::io::Result<()> {
-    let dlg_width = 400;
-    // Generate QR Code image
-    let code = QrCode::new(ssr_url.as_bytes()).map_err(|e| std::io::Error::other(format!("QR code generation error: {e}")))?;
-    let img = code.render::<Luma<u8>>().min_dimensions(256, 256).build();
-    // Convert image::ImageBuffer to PNG bytes
-    use image::DynamicImage;
-    use std::io::Cursor;
-    let mut png_bytes: Vec<u8> = Vec::new();
-    let dyn_img = DynamicImage::ImageLuma8(img);
-    use image::ImageFormat;
-    let mut writer = Cursor::new(&mut png_bytes);
-    dyn_img
-        .write_to(&mut writer, ImageFormat::Png)
-        .map_err(|e| std::io::Error::other(format!("Image encoding error: {e}")))?;
+use fltk::{
+    button::{Button, CheckButton},
+    frame::Frame,
+    image::PngImage,
+    misc::Spinner,
+    prelude::*,
+    text::{TextBuffer, TextDisplay, WrapMode},
+    window::Window,
+};
+use image::{GrayImage, Luma};
+use qrcode::{QrCode, render::svg};
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
+
+/// Side of the code shown in the dialog
+const PREVIEW_SIZE: i32 = 256;
+
+const DEFAULT_EXPORT_SIZE: f64 = 512.0;
+
+/// Show the link as a QR code, or as a numbered sequence of codes to page through when it's too long for one.
+/// The current code can be saved as PNG or SVG or copied as an image, the link text is only shown on request.
+pub fn qr_code_dialog(parent: &Window, title: &str, link: &str) -> std::io::Result<()> {
+    let dlg_width = 420;
+    let parts = Rc::new(crate::qr_parts::split_for_qr(link));
+    let previews = parts.iter().map(|part| preview_png(part)).collect::<std::io::Result<Vec<_>>>()?;
+    let multi_part = parts.len() > 1;
+    let dlg_height = if multi_part { 590 } else { 545 };
 
     let x = parent.x() + (parent.width() - dlg_width) / 2;
-    let y = parent.y() + (parent.height() - dlg_width) / 2;
-    let mut win = Window::new(x, y, dlg_width, dlg_width, title);
+    let y = parent.y() + (parent.height() - dlg_height) / 2;
+    let mut win = Window::new(x, y, dlg_width, dlg_height, title);
     let icon = crate::util::get_embedded_main_icon()?;
     win.set_icon(Some(icon));
 
-    let mut frame = Frame::new(72, 72, 256, 256, "");
-    let png = PngImage::from_data(&png_bytes).map_err(|e| std::io::Error::other(format!("FLTK image error: {e}")))?;
-    frame.set_image(Some(png));
+    let mut frame = Frame::new((dlg_width - PREVIEW_SIZE) / 2, 10, PREVIEW_SIZE, PREVIEW_SIZE, "");
+    frame.set_image(Some(previews[0].clone()));
+    let page = Rc::new(Cell::new(0usize));
+    let mut row_y = PREVIEW_SIZE + 20;
+
+    if multi_part {
+        let mut page_label = Frame::new(130, row_y, dlg_width - 260, 35, None);
+        let mut prev_btn = Button::new(20, row_y, 100, 35, "@< Previous");
+        let mut next_btn = Button::new(dlg_width - 120, row_y, 100, 35, "Next @>");
+        let total = previews.len();
+        let previews = Rc::new(previews);
+
+        page_label.set_label(&format!("Part 1 of {total}"));
+        let mut show_page = {
+            let (mut frame, mut page_label, page) = (frame.clone(), page_label.clone(), page.clone());
+            move |step: isize| {
+                let current = (page.get() as isize + step).rem_euclid(total as isize) as usize;
+                page.set(current);
+                frame.set_image(Some(previews[current].clone()));
+                frame.redraw();
+                page_label.set_label(&format!("Part {} of {total}", current + 1));
+            }
+        };
+        let mut show_prev = show_page.clone();
+        prev_btn.set_callback(move |_| show_prev(-1));
+        next_btn.set_callback(move |_| show_page(1));
+        row_y += 45;
+    }
+
+    let mut size_label = Frame::new(20, row_y, 170, 30, "Export size (pixels):");
+    size_label.set_align(fltk::enums::Align::Right | fltk::enums::Align::Inside);
+    let mut export_size = Spinner::new(200, row_y, 100, 30, None);
+    export_size.set_range(128.0, 4096.0);
+    export_size.set_step(64.0);
+    export_size.set_value(DEFAULT_EXPORT_SIZE);
+    row_y += 40;
+
+    let mut save_png_btn = Button::new(20, row_y, 120, 35, "Save PNG...");
+    let mut save_svg_btn = Button::new(150, row_y, 120, 35, "Save SVG...");
+    let mut copy_image_btn = Button::new(280, row_y, 120, 35, "Copy Image");
+    row_y += 45;
+
+    let mut show_text = CheckButton::new(20, row_y, 250, 30, "Show link text (contains secrets)");
+    let mut copy_link_btn = Button::new(280, row_y, 120, 35, "Copy Link");
+    row_y += 40;
+
+    let mut text_buffer = TextBuffer::default();
+    let mut text_display = TextDisplay::new(20, row_y, dlg_width - 40, dlg_height - row_y - 10, None);
+    text_display.set_buffer(text_buffer.clone());
+    text_display.wrap_mode(WrapMode::AtBounds, 0);
+    text_buffer.set_text("(hidden)");
+
     win.end();
     win.show();
+
+    let current_part = {
+        let (parts, page) = (parts.clone(), page.clone());
+        move || parts[page.get()].clone()
+    };
+    // Suggested file name, numbered for split codes
+    let file_name = {
+        let page = page.clone();
+        let total = parts.len();
+        move |ext: &str| match total {
+            1 => format!("overtls-node.{ext}"),
+            _ => format!("overtls-node-part{}of{total}.{ext}", page.get() + 1),
+        }
+    };
+
+    let (part, spin, name) = (current_part.clone(), export_size.clone(), file_name.clone());
+    save_png_btn.set_callback(move |_| {
+        let Some(path) = file_chooser_save(&name("png"), "PNG Image", "png") else {
+            return;
+        };
+        let result = render_image(&part(), spin.value() as u32).and_then(|img| {
+            img.save_with_format(&path, image::ImageFormat::Png)
+                .map_err(|e| std::io::Error::other(format!("Failed to save image: {e}")))
+        });
+        report_save(result, &path);
+    });
+
+    let (part, spin, name) = (current_part.clone(), export_size.clone(), file_name);
+    save_svg_btn.set_callback(move |_| {
+        let Some(path) = file_chooser_save(&name("svg"), "SVG Image", "svg") else {
+            return;
+        };
+        let result = render_svg(&part(), spin.value() as u32).and_then(|svg| std::fs::write(&path, svg));
+        report_save(result, &path);
+    });
+
+    // On Linux the clipboard content is served by its owner, keep it alive as long as the dialog
+    let clipboard: Rc<RefCell<Option<arboard::Clipboard>>> = Rc::new(RefCell::new(None));
+    let (part, spin) = (current_part, export_size.clone());
+    copy_image_btn.set_callback(move |_| {
+        let result = render_image(&part(), spin.value() as u32).and_then(|img| {
+            let clipboard_error = |e: arboard::Error| std::io::Error::other(format!("Clipboard error: {e}"));
+            let mut clipboard = clipboard.borrow_mut();
+            if clipboard.is_none() {
+                *clipboard = Some(arboard::Clipboard::new().map_err(clipboard_error)?);
+            }
+            let rgba = image::DynamicImage::ImageLuma8(img).into_rgba8();
+            let data = arboard::ImageData {
+                width: rgba.width() as usize,
+                height: rgba.height() as usize,
+                bytes: rgba.into_raw().into(),
+            };
+            if let Some(clipboard) = clipboard.as_mut() {
+                clipboard.set_image(data).map_err(clipboard_error)?;
+            }
+            Ok(())
+        });
+        if let Err(e) = result {
+            rfd::MessageDialog::new()
+                .set_title("Error")
+                .set_description(format!("Failed to copy the QR code: {e}"))
+                .set_level(rfd::MessageLevel::Error)
+                .show();
+        }
+    });
+
+    let link_text = link.to_string();
+    copy_link_btn.set_callback(move |_| fltk::app::copy(&link_text));
+
+    let link_text = link.to_string();
+    show_text.set_callback(move |check| {
+        text_buffer.set_text(if check.value() { link_text.as_str() } else { "(hidden)" });
+    });
     Ok(())
 }
+
+fn file_chooser_save(file_name: &str, filter: &str, ext: &str) -> Option<std::path::PathBuf> {
+    rfd::FileDialog::new()
+        .set_title("Save QR Code")
+        .set_file_name(file_name)
+        .add_filter(filter, &[ext])
+        .save_file()
+}
+
+fn report_save(result: std::io::Result<()>, path: &std::path::Path) {
+    match result {
+        Ok(()) => {
+            log::debug!("QR code saved to: {}", path.display());
+            crate::states_manager::set_file_owner_if_needed(path);
+        }
+        Err(e) => {
+            rfd::MessageDialog::new()
+                .set_title("Error")
+                .set_description(format!("Failed to save the QR code: {e}"))
+                .set_level(rfd::MessageLevel::Error)
+                .show();
+        }
+    }
+}
+
+fn qr_code(text: &str) -> std::io::Result<QrCode> {
+    QrCode::new(text.as_bytes()).map_err(|e| std::io::Error::other(format!("QR code generation error: {e}")))
+}
+
+/// The code at least `size` pixels wide, a bit more when the modules don't divide it evenly
+fn render_image(text: &str, size: u32) -> std::io::Result<GrayImage> {
+    Ok(qr_code(text)?.render::<Luma<u8>>().min_dimensions(size, size).build())
+}
+
+fn render_svg(text: &str, size: u32) -> std::io::Result<String> {
+    Ok(qr_code(text)?.render::<svg::Color>().min_dimensions(size, size).build())
+}
+
+fn preview_png(text: &str) -> std::io::Result<PngImage> {
+    let img = render_image(text, PREVIEW_SIZE as u32)?;
+    // Convert image::ImageBuffer to PNG bytes
+    let mut png_bytes: Vec<u8> = Vec::new();
+    image::DynamicImage::ImageLuma8(img)
+        .write_to(&mut std::io::Cursor::new(&mut png_bytes), image::ImageFormat::Png)
+        .map_err(|e| std::io::Error::other(format!("Image encoding error: {e}")))?;
+    let mut png = PngImage::from_data(&png_bytes).map_err(|e| std::io::Error::other(format!("FLTK image error: {e}")))?;
+    // Larger codes render bigger than the minimum, keep them in the frame
+    png.scale(PREVIEW_SIZE, PREVIEW_SIZE, true, false);
+    Ok(png)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exported_code_has_requested_size_and_decodes() {
+        let link = "ssr://example";
+        let img = render_image(link, 512).unwrap();
+        assert!(img.width() >= 512 && img.width() < 600);
+
+        let mut prepared = rqrr::PreparedImage::prepare(img);
+        let grids = prepared.detect_grids();
+        assert_eq!(grids.len(), 1);
+        assert_eq!(grids[0].decode().unwrap().1, link);
+
+        let svg = render_svg(link, 512).unwrap();
+        assert!(svg.contains("<svg"));
+    }
+}
@@ -0,0 +1,157 @@
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+
+/// Prefix of one part of a link too long for a single QR code: `overtls-part:<index>/<total>/<group>/<chunk>`
+pub const PART_PREFIX: &str = "overtls-part:";
+
+/// Longest text put in one QR code, longer links are split. Denser codes are hard to scan from a screen.
+pub const MAX_QR_TEXT_LEN: usize = 800;
+
+/// Parts of different links are told apart by this many hex digits of the link's SHA-256
+const GROUP_ID_LEN: usize = 8;
+
+/// Incomplete sequences are forgotten once no part of them was scanned for this long
+pub const PENDING_TTL: std::time::Duration = std::time::Duration::from_secs(10 * 60);
+
+/// Texts to encode as a numbered sequence of QR codes, the link itself when it fits in one
+pub fn split_for_qr(link: &str) -> Vec<String> {
+    if link.len() <= MAX_QR_TEXT_LEN {
+        return vec![link.to_string()];
+    }
+    let group = group_id(link);
+    let chars: Vec<char> = link.chars().collect();
+    // Room left for the header, with up to 3 digit indices
+    let chunk_len = MAX_QR_TEXT_LEN - PART_PREFIX.len() - GROUP_ID_LEN - 10;
+    let chunks: Vec<String> = chars.chunks(chunk_len).map(|c| c.iter().collect()).collect();
+    let total = chunks.len();
+    chunks
+        .into_iter()
+        .enumerate()
+        .map(|(i, chunk)| format!("{PART_PREFIX}{}/{total}/{group}/{chunk}", i + 1))
+        .collect()
+}
+
+fn group_id(link: &str) -> String {
+    let hash = format!("{:x}", Sha256::digest(link.as_bytes()));
+    hash[..GROUP_ID_LEN].to_string()
+}
+
+struct PartialLink {
+    total: usize,
+    chunks: BTreeMap<usize, String>,
+    /// When the last part of the sequence was scanned
+    updated: std::time::Instant,
+}
+
+/// Collects the parts of split links, scanned in any order and from any number of images
+#[derive(Default)]
+pub struct PartAssembler {
+    pending: HashMap<String, PartialLink>,
+}
+
+impl PartAssembler {
+    /// Store a part, returns the whole link once its last missing part arrives, `None` while parts are missing
+    pub fn add(&mut self, text: &str) -> Result<Option<String>, String> {
+        let invalid = || format!("Invalid QR code part '{}'", text.chars().take(40).collect::<String>());
+        let header = text.strip_prefix(PART_PREFIX).ok_or_else(invalid)?;
+        let mut fields = header.splitn(4, '/');
+        let (Some(index), Some(total), Some(group), Some(chunk)) = (fields.next(), fields.next(), fields.next(), fields.next()) else {
+            return Err(invalid());
+        };
+        let (Ok(index), Ok(total)) = (index.parse::<usize>(), total.parse::<usize>()) else {
+            return Err(invalid());
+        };
+        if total == 0 || index == 0 || index > total {
+            return Err(invalid());
+        }
+        let partial = self.pending.entry(group.to_string()).or_insert_with(|| PartialLink {
+            total,
+            chunks: BTreeMap::new(),
+            updated: std::time::Instant::now(),
+        });
+        if partial.total != total {
+            return Err(format!(
+                "QR code part {index}/{total} doesn't match the other parts of its sequence"
+            ));
+        }
+        partial.chunks.insert(index, chunk.to_string());
+        partial.updated = std::time::Instant::now();
+        if partial.chunks.len() < total {
+            return Ok(None);
+        }
+        let partial = self.pending.remove(group).ok_or_else(invalid)?;
+        let link: String = partial.chunks.into_values().collect();
+        if group_id(&link) != group {
+            return Err("The reassembled QR code parts are corrupted".to_string());
+        }
+        Ok(Some(link))
+    }
+
+    /// Which parts are still missing, e.g. "2, 3 of 3", for each incomplete sequence
+    pub fn missing(&self) -> Vec<String> {
+        self.pending
+            .values()
+            .map(|partial| {
+                let missing: Vec<_> = (1..=partial.total)
+                    .filter(|i| !partial.chunks.contains_key(i))
+                    .map(|i| i.to_string())
+                    .collect();
+                format!("{} of {}", missing.join(", "), partial.total)
+            })
+            .collect()
+    }
+
+    /// Forget the incomplete sequences none of whose parts was scanned within `ttl`
+    pub fn expire(&mut self, ttl: std::time::Duration) {
+        self.pending.retain(|_, partial| partial.updated.elapsed() < ttl);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_links_are_not_split() {
+        assert_eq!(split_for_qr("ssr://short"), vec!["ssr://short".to_string()]);
+    }
+
+    #[test]
+    fn reassemble_parts_in_any_order() {
+        let link = format!("overtls://v1/json/{}", "A".repeat(3 * MAX_QR_TEXT_LEN));
+        let parts = split_for_qr(&link);
+        assert_eq!(parts.len(), 4);
+        assert!(parts.iter().all(|p| p.len() <= MAX_QR_TEXT_LEN && p.starts_with(PART_PREFIX)));
+
+        let mut assembler = PartAssembler::default();
+        assert_eq!(assembler.add(&parts[2]), Ok(None));
+        assert_eq!(assembler.add(&parts[0]), Ok(None));
+        // Scanning a part twice does no harm
+        assert_eq!(assembler.add(&parts[0]), Ok(None));
+        assert_eq!(assembler.missing(), vec!["2, 4 of 4".to_string()]);
+        assert_eq!(assembler.add(&parts[3]), Ok(None));
+        assert_eq!(assembler.add(&parts[1]), Ok(Some(link)));
+        assert!(assembler.missing().is_empty());
+    }
+
+    #[test]
+    fn reject_malformed_parts() {
+        let mut assembler = PartAssembler::default();
+        assert!(assembler.add("overtls-part:0/2/abcdef01/x").is_err());
+        assert!(assembler.add("overtls-part:3/2/abcdef01/x").is_err());
+        assert!(assembler.add("overtls-part:1/2/abcdef01").is_err());
+        assert!(assembler.add("overtls-part:1/1/abcdef01/tampered").is_err());
+    }
+
+    #[test]
+    fn incomplete_sequences_expire() {
+        let parts = split_for_qr(&"B".repeat(2 * MAX_QR_TEXT_LEN));
+        let mut assembler = PartAssembler::default();
+        assert_eq!(assembler.add(&parts[0]), Ok(None));
+        assembler.expire(PENDING_TTL);
+        assert_eq!(assembler.missing().len(), 1);
+        assembler.expire(std::time::Duration::ZERO);
+        assert!(assembler.missing().is_empty());
+        assert_eq!(assembler.add(&parts[1]), Ok(None));
+    }
+}
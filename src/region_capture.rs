@@ -0,0 +1,105 @@
+use fltk::{
+    app,
+    enums::{Color, ColorDepth, Cursor, Event, Key},
+    frame::Frame,
+    image::RgbImage,
+    prelude::{GroupExt, ImageExt, WidgetBase, WidgetExt, WindowExt},
+    window::Window,
+};
+use std::{cell::RefCell, rc::Rc};
+
+/// Regions smaller than this are taken as a plain click, i.e. cancelled
+const MIN_SELECTION: i32 = 8;
+
+/// Freeze the display under the mouse and let the user drag a rectangle over it.
+/// The selected part of the screenshot is sent via channel, nothing when cancelled with Escape.
+pub fn select_region(tx: std::sync::mpsc::Sender<image::DynamicImage>) -> std::io::Result<()> {
+    // Wayland lets clients neither grab the screen nor cover it with a window, the portal picks the region instead
+    #[cfg(target_os = "linux")]
+    if crate::paste_operations::is_wayland_session() {
+        match crate::paste_operations::portal_screenshot(true) {
+            Ok(region) => {
+                let _ = tx.send(region);
+                app::awake();
+                return Ok(());
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => return Ok(()),
+            Err(e) => log::warn!("Portal screenshot failed, falling back to direct capture: {e}"),
+        }
+    }
+
+    let (mouse_x, mouse_y) = app::get_mouse();
+    let screen = app::screen_num(mouse_x, mouse_y);
+    let (sx, sy, sw, sh) = app::screen_xywh(screen);
+    let shot = crate::paste_operations::screenshot_to_image(screen.max(0) as usize)?;
+
+    let rgb = shot.to_rgb8();
+    let mut background = RgbImage::new(rgb.as_raw(), rgb.width() as i32, rgb.height() as i32, ColorDepth::Rgb8)
+        .map_err(|e| std::io::Error::other(format!("FLTK image error: {e}")))?;
+    // The screenshot is in physical pixels, the window in logical ones
+    background.scale(sw, sh, false, true);
+
+    let mut win = Window::new(sx, sy, sw, sh, "Select Region");
+    win.set_border(false);
+    let mut frame = Frame::new(0, 0, sw, sh, "");
+    win.end();
+
+    let selection: Rc<RefCell<Option<(i32, i32, i32, i32)>>> = Rc::new(RefCell::new(None));
+    let selection_draw = selection.clone();
+    frame.draw(move |f| {
+        background.draw(f.x(), f.y(), f.w(), f.h());
+        if let Some((x, y, w, h)) = *selection_draw.borrow() {
+            fltk::draw::set_draw_color(Color::Red);
+            fltk::draw::set_line_style(fltk::draw::LineStyle::Solid, 2);
+            fltk::draw::draw_rect(x, y, w, h);
+            fltk::draw::set_line_style(fltk::draw::LineStyle::Solid, 0);
+        }
+    });
+
+    let mut start = (0, 0);
+    let mut tx = Some(tx);
+    win.handle(move |w, ev| match ev {
+        Event::Push => {
+            start = app::event_coords();
+            *selection.borrow_mut() = None;
+            true
+        }
+        Event::Drag => {
+            let (x, y) = app::event_coords();
+            *selection.borrow_mut() = Some((start.0.min(x), start.1.min(y), (x - start.0).abs(), (y - start.1).abs()));
+            w.redraw();
+            true
+        }
+        Event::Released => {
+            let rect = selection.borrow_mut().take();
+            w.hide();
+            // Deferred, the window is still handling this event
+            app::delete_widget(w.clone());
+            if let Some((x, y, rw, rh)) = rect.filter(|r| r.2 >= MIN_SELECTION && r.3 >= MIN_SELECTION)
+                && let Some(tx) = tx.take()
+            {
+                let scale_x = shot.width() as f64 / sw as f64;
+                let scale_y = shot.height() as f64 / sh as f64;
+                let region = shot.crop_imm(
+                    (x as f64 * scale_x) as u32,
+                    (y as f64 * scale_y) as u32,
+                    (rw as f64 * scale_x).ceil() as u32,
+                    (rh as f64 * scale_y).ceil() as u32,
+                );
+                let _ = tx.send(region);
+                app::awake();
+            }
+            true
+        }
+        Event::KeyDown if app::event_key() == Key::Escape => {
+            w.hide();
+            app::delete_widget(w.clone());
+            true
+        }
+        _ => false,
+    });
+
+    win.show();
+    win.set_cursor(Cursor::Cross);
+    Ok(())
+}
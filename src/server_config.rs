@@ -0,0 +1,118 @@
+use crate::OverTlsNode;
+use overtls::ClientConfig;
+
+/// Port clients connect to when the server only listens on loopback behind a reverse proxy
+const REVERSE_PROXY_TLS_PORT: u16 = 443;
+
+/// A config written for running the overtls server, it can't be run as a node as is
+pub fn is_server_config(node: &OverTlsNode) -> bool {
+    node.client.is_none() && node.server.is_some()
+}
+
+/// Guess the public host clients should connect to: the listening address when it's a public one,
+/// otherwise the domain of a Let's Encrypt certificate, e.g. `/etc/letsencrypt/live/example.com/fullchain.pem`
+pub fn inferred_public_host(node: &OverTlsNode) -> Option<String> {
+    let server = node.server.as_ref()?;
+    let listen_host = server.listen_host.trim();
+    let is_public = match listen_host.parse::<std::net::IpAddr>() {
+        Ok(ip) => !ip.is_unspecified() && !ip.is_loopback(),
+        Err(_) => !listen_host.is_empty() && !listen_host.eq_ignore_ascii_case("localhost"),
+    };
+    if is_public {
+        return Some(listen_host.to_string());
+    }
+    let certfile = std::path::Path::new(server.certfile.as_ref()?);
+    let mut components = certfile.components().map(|c| c.as_os_str().to_string_lossy());
+    components.find(|c| c == "live")?;
+    components
+        .next()
+        .filter(|domain| domain.contains('.'))
+        .map(|domain| domain.into_owned())
+}
+
+/// Build the client node matching a server config, connecting to `public_host`
+pub fn derive_client_node(server_node: &OverTlsNode, public_host: &str) -> std::io::Result<OverTlsNode> {
+    let server = server_node
+        .server
+        .as_ref()
+        .ok_or_else(|| std::io::Error::other("Not a server config"))?;
+    let public_host = public_host.trim();
+    if public_host.is_empty() {
+        return Err(std::io::Error::other("No public host given"));
+    }
+    let behind_proxy = match server.listen_host.parse::<std::net::IpAddr>() {
+        Ok(ip) => ip.is_loopback(),
+        Err(_) => server.listen_host.eq_ignore_ascii_case("localhost"),
+    };
+
+    let mut client = ClientConfig::default();
+    client.server_host = public_host.to_string();
+    client.server_port = if behind_proxy { REVERSE_PROXY_TLS_PORT } else { server.listen_port };
+    // The certificate is issued for a domain, connecting by IP still needs it for SNI
+    client.server_domain = match public_host.parse::<std::net::IpAddr>() {
+        Ok(_) => inferred_public_host(server_node).filter(|host| host.parse::<std::net::IpAddr>().is_err()),
+        Err(_) => Some(public_host.to_string()),
+    };
+    // A proxy in front terminates TLS, whatever the server itself does
+    client.disable_tls = Some(!behind_proxy && server.disable_tls.unwrap_or(false));
+
+    let remarks = server_node
+        .remarks
+        .clone()
+        .filter(|r| !r.is_empty())
+        .unwrap_or_else(|| public_host.to_string());
+    let node = OverTlsNode {
+        remarks: Some(remarks),
+        tunnel_path: server_node.tunnel_path.clone(),
+        client: Some(client),
+        ..OverTlsNode::default()
+    };
+    // Validate a copy, the defaults it fills in are applied again when the node is run
+    node.clone()
+        .check_correctness(false)
+        .map_err(|e| std::io::Error::other(format!("Derived client config is invalid: {e}")))?;
+    Ok(node)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_server() -> OverTlsNode {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/server_config.json");
+        OverTlsNode::from_config_file(path).unwrap()
+    }
+
+    #[test]
+    fn detect_server_config() {
+        let server = sample_server();
+        assert!(is_server_config(&server));
+        let client = derive_client_node(&server, "example.com").unwrap();
+        assert!(!is_server_config(&client));
+    }
+
+    #[test]
+    fn infer_host_from_certificate_path() {
+        let server = sample_server();
+        assert_eq!(inferred_public_host(&server).as_deref(), Some("example.com"));
+
+        let mut unknown = server.clone();
+        unknown.server.as_mut().unwrap().certfile = None;
+        assert_eq!(inferred_public_host(&unknown), None);
+    }
+
+    #[test]
+    fn derive_client_behind_reverse_proxy() {
+        let server = sample_server();
+        let node = derive_client_node(&server, "203.0.113.7").unwrap();
+        let client = node.client.as_ref().unwrap();
+        assert_eq!(client.server_host, "203.0.113.7");
+        assert_eq!(client.server_port, REVERSE_PROXY_TLS_PORT);
+        assert_eq!(client.server_domain.as_deref(), Some("example.com"));
+        assert_eq!(
+            serde_json::to_value(&node.tunnel_path).unwrap(),
+            serde_json::to_value(&server.tunnel_path).unwrap()
+        );
+        assert!(derive_client_node(&server, "  ").is_err());
+    }
+}
@@ -1,4 +1,4 @@
-use crate::states_manager::SystemSettings;
+use crate::{share_link::ShareLinkFormat, states_manager::SystemSettings};
 use fltk::{
     button::{Button, CheckButton},
     enums::Align,
@@ -110,6 +110,9 @@ pub fn show_settings_dialog(win: &Window, system_settings: &SystemSettings, tx:
     let mut listen_password = add_row_input!("Listen Password", listen_password, flex_common);
     let mut pool_max_size = add_row_input!("Connection Pool Max Size", pool_max_size, flex_common);
     let mut cache_dns = add_row_check!("Cache DNS", cache_dns, flex_common);
+    let share_link_options = ShareLinkFormat::ALL.map(ShareLinkFormat::label).join("|");
+    let mut share_link_format = add_row_choice!("Share Link Format", share_link_format, flex_common, &share_link_options);
+    let mut watch_clipboard = add_row_check!("Offer to Import Copied Nodes", watch_clipboard, flex_common);
 
     tab_common.end();
 
@@ -172,6 +175,9 @@ pub fn show_settings_dialog(win: &Window, system_settings: &SystemSettings, tx:
     listen_password.set_value(system_settings.listen_password.as_deref().unwrap_or(""));
     pool_max_size.set_value(&system_settings.pool_max_size.to_string());
     cache_dns.set_value(system_settings.cache_dns);
+    let format = system_settings.share_link_format.unwrap_or_default();
+    share_link_format.set_value(ShareLinkFormat::ALL.iter().position(|f| *f == format).unwrap_or(0) as i32);
+    watch_clipboard.set_value(system_settings.watch_clipboard.unwrap_or_default());
 
     let tun2proxy_cfg = system_settings.tun2proxy.clone().unwrap_or_default();
 
@@ -214,6 +220,8 @@ pub fn show_settings_dialog(win: &Window, system_settings: &SystemSettings, tx:
         };
         let pool_max_size_val = pool_max_size.value().parse().unwrap_or(8);
         let cache_dns_val = cache_dns.value();
+        let share_link_format_val = ShareLinkFormat::ALL.get(share_link_format.value() as usize).copied();
+        let watch_clipboard_val = watch_clipboard.value();
 
         // Tun2proxy Tab values
         let tun2proxy_enable_val = tun2proxy_enable.value();
@@ -259,6 +267,8 @@ pub fn show_settings_dialog(win: &Window, system_settings: &SystemSettings, tx:
             overtls_log_level: overtls_log_level_val,
             tun2proxy_log_level: tun2proxy_log_level_val,
             log_auto_scroll: Some(log_auto_scroll_val),
+            share_link_format: share_link_format_val,
+            watch_clipboard: Some(watch_clipboard_val),
         };
         let _ = tx.send(new_settings);
         dlg_cb.hide();
@@ -0,0 +1,131 @@
+use crate::OverTlsNode;
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+
+pub const OVERTLS_SCHEME: &str = "overtls://";
+pub const SSR_SCHEME: &str = "ssr://";
+
+/// Every scheme `parse_share_link` understands
+pub const SHARE_LINK_SCHEMES: [&str; 2] = [OVERTLS_SCHEME, SSR_SCHEME];
+
+const PLAIN_PREFIX: &str = "v1/json/";
+const DEFLATE_PREFIX: &str = "v1/deflate/";
+
+/// Upper bound of a decompressed link, so a crafted link can't exhaust memory
+const MAX_DECODED_LEN: u64 = 1024 * 1024;
+
+/// How nodes are shared by copy and QR code.
+///
+/// `overtls://` links carry the full config as JSON, base64url encoded and optionally deflated.
+/// `ssr://` links are understood by other clients but lose what the SSR format has no field for.
+/// They stay the default, so that links keep working with clients that don't know `overtls://`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ShareLinkFormat {
+    OverTlsCompressed,
+    OverTls,
+    #[default]
+    Ssr,
+}
+
+impl ShareLinkFormat {
+    pub const ALL: [ShareLinkFormat; 3] = [ShareLinkFormat::OverTlsCompressed, ShareLinkFormat::OverTls, ShareLinkFormat::Ssr];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ShareLinkFormat::OverTlsCompressed => "overtls:// compressed",
+            ShareLinkFormat::OverTls => "overtls:// plain",
+            ShareLinkFormat::Ssr => "ssr:// (compatibility)",
+        }
+    }
+}
+
+pub fn generate_share_link(node: &OverTlsNode, format: ShareLinkFormat) -> std::io::Result<String> {
+    if format == ShareLinkFormat::Ssr {
+        return node
+            .generate_ssr_url()
+            .map_err(|e| std::io::Error::other(format!("Failed to generate ssr:// link: {e}")));
+    }
+    // Only the client side is shared
+    let client_only = OverTlsNode {
+        server: None,
+        ..node.clone()
+    };
+    let json = serde_json::to_string(&client_only).map_err(|e| std::io::Error::other(format!("Failed to serialize node: {e}")))?;
+    if format == ShareLinkFormat::OverTls {
+        return Ok(format!("{OVERTLS_SCHEME}{PLAIN_PREFIX}{}", URL_SAFE_NO_PAD.encode(json)));
+    }
+    let mut encoder = flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::best());
+    encoder.write_all(json.as_bytes())?;
+    let compressed = encoder.finish()?;
+    Ok(format!("{OVERTLS_SCHEME}{DEFLATE_PREFIX}{}", URL_SAFE_NO_PAD.encode(compressed)))
+}
+
+/// Parse an `overtls://` or `ssr://` link
+pub fn parse_share_link(link: &str) -> std::io::Result<OverTlsNode> {
+    let invalid_data = |msg: String| std::io::Error::new(std::io::ErrorKind::InvalidData, msg);
+    let link = link.trim();
+    let Some(rest) = link.strip_prefix(OVERTLS_SCHEME) else {
+        return OverTlsNode::from_ssr_url(link).map_err(|e| invalid_data(e.to_string()));
+    };
+    let decode = |data: &str| {
+        URL_SAFE_NO_PAD
+            .decode(data)
+            .map_err(|e| invalid_data(format!("Invalid overtls:// link: {e}")))
+    };
+    let json = if let Some(data) = rest.strip_prefix(PLAIN_PREFIX) {
+        String::from_utf8(decode(data)?).map_err(|e| invalid_data(format!("Invalid overtls:// link: {e}")))?
+    } else if let Some(data) = rest.strip_prefix(DEFLATE_PREFIX) {
+        let compressed = decode(data)?;
+        let mut json = String::new();
+        flate2::read::DeflateDecoder::new(compressed.as_slice())
+            .take(MAX_DECODED_LEN)
+            .read_to_string(&mut json)
+            .map_err(|e| invalid_data(format!("Invalid overtls:// link: {e}")))?;
+        json
+    } else {
+        return Err(invalid_data("Unsupported overtls:// link version".to_string()));
+    };
+    OverTlsNode::from_json_str(&json).map_err(|e| invalid_data(format!("Invalid overtls:// link: {e}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_node() -> OverTlsNode {
+        let mut node = crate::states_manager::test_fixtures::fixture_node();
+        // What ssr:// links can't carry
+        let client = node.client.as_mut().unwrap();
+        client.cafile = Some("-----BEGIN CERTIFICATE-----\nMIIB\n-----END CERTIFICATE-----\n".into());
+        client.dangerous_mode = Some(true);
+        client.disable_tls = Some(true);
+        node
+    }
+
+    #[test]
+    fn overtls_links_round_trip_the_full_config() {
+        let node = sample_node();
+        for format in [ShareLinkFormat::OverTls, ShareLinkFormat::OverTlsCompressed] {
+            let link = generate_share_link(&node, format).unwrap();
+            assert!(link.starts_with(OVERTLS_SCHEME));
+            assert!(!link.contains(char::is_whitespace));
+            let parsed = parse_share_link(&link).unwrap();
+            assert_eq!(serde_json::to_value(&parsed).unwrap(), serde_json::to_value(&node).unwrap());
+        }
+    }
+
+    #[test]
+    fn ssr_links_still_parse() {
+        let node = sample_node();
+        let link = generate_share_link(&node, ShareLinkFormat::Ssr).unwrap();
+        assert!(link.starts_with(SSR_SCHEME));
+        assert_eq!(parse_share_link(&link).unwrap().remarks, node.remarks);
+    }
+
+    #[test]
+    fn reject_malformed_overtls_links() {
+        assert!(parse_share_link("overtls://v1/deflate/!!!").is_err());
+        assert!(parse_share_link("overtls://v9/json/e30").is_err());
+    }
+}
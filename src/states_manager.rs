@@ -1,6 +1,15 @@
-use crate::OverTlsNode;
+use crate::{
+    OverTlsNode,
+    share_link::ShareLinkFormat,
+    subscriptions::{Subscription, SubscriptionId},
+    vault::{EncryptedBlob, Vault},
+};
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::{
+    cell::{Ref, RefCell, RefMut},
+    path::PathBuf,
+    rc::Rc,
+};
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct WindowState {
@@ -71,6 +80,12 @@ pub struct SystemSettings {
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub log_auto_scroll: Option<bool>, // log auto scroll
+
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub share_link_format: Option<ShareLinkFormat>, // format of copied links and QR codes
+
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub watch_clipboard: Option<bool>, // offer to import nodes copied to the clipboard
 }
 
 impl Default for SystemSettings {
@@ -92,91 +107,1391 @@ impl Default for SystemSettings {
             overtls_log_level: Some("Debug".to_string()),
             tun2proxy_log_level: Some("Debug".to_string()),
             log_auto_scroll: Some(true),
+            share_link_format: Some(ShareLinkFormat::default()),
+            watch_clipboard: Some(false),
         }
     }
 }
 
-#[derive(Default, Clone, Serialize, Deserialize)]
+/// Version of the `config.json` layout written by this build.
+/// Bump it together with a new step in `MIGRATIONS` whenever a field is renamed or re-typed.
+pub const SCHEMA_VERSION: u32 = 3;
+
+/// Name of the profile created for existing configs and fresh installs
+pub const DEFAULT_PROFILE_NAME: &str = "Default";
+
+/// Persistent identifier of a stored node
+pub type NodeId = uuid::Uuid;
+
+/// A node together with its persistent identifier.
+/// Selection, open dialogs and the running node refer to nodes by `id`, never by row index,
+/// so adding or deleting rows can't redirect an edit to another node.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct StoredNode {
+    pub id: NodeId,
+    pub node: OverTlsNode,
+    /// The subscription this node comes from, its refreshes replace the node
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub subscription_id: Option<SubscriptionId>,
+}
+
+impl StoredNode {
+    pub fn new(node: OverTlsNode) -> Self {
+        StoredNode {
+            id: NodeId::new_v4(),
+            node,
+            subscription_id: None,
+        }
+    }
+}
+
+/// What makes two nodes the same server, whatever their remarks or how their fields are written:
+/// host (case-insensitive), port, tunnel paths (ignoring surrounding slashes and order) and client id.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct NodeIdentity {
+    host: String,
+    port: u16,
+    tunnel_paths: Vec<String>,
+    client_id: Option<String>,
+}
+
+impl NodeIdentity {
+    /// `None` for configs without a client part, they are never taken as duplicates
+    pub fn of(node: &OverTlsNode) -> Option<Self> {
+        let client = node.client.as_ref()?;
+        let mut tunnel_paths: Vec<String> = match serde_json::to_value(&node.tunnel_path).ok()? {
+            serde_json::Value::String(path) => vec![path],
+            serde_json::Value::Array(paths) => paths.iter().filter_map(|p| p.as_str().map(str::to_string)).collect(),
+            _ => Vec::new(),
+        };
+        for path in tunnel_paths.iter_mut() {
+            *path = path.trim().trim_matches('/').to_string();
+        }
+        tunnel_paths.sort();
+        tunnel_paths.dedup();
+        Some(NodeIdentity {
+            host: client.server_host.trim().trim_end_matches('.').to_ascii_lowercase(),
+            port: client.server_port,
+            tunnel_paths,
+            client_id: client
+                .client_id
+                .as_ref()
+                .map(|id| id.trim().to_ascii_lowercase())
+                .filter(|id| !id.is_empty()),
+        })
+    }
+}
+
+/// What to do with an imported node that has the same identity as a node already in the list
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DuplicatePolicy {
+    /// Keep the node in the list, drop the imported one
+    Skip,
+    /// Overwrite the node in the list, keeping its position and identifier
+    Replace,
+    /// Add the imported node anyway
+    KeepBoth,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct AppState {
+    #[serde(default)]
+    pub schema_version: u32,
+
     pub window: WindowState,
 
     #[serde(skip_serializing_if = "Option::is_none", default)]
-    pub current_node_index: Option<usize>,
+    pub current_node_id: Option<NodeId>,
 
     #[serde(skip_serializing_if = "Option::is_none", default)]
     pub current_selection_path: Option<PathBuf>,
 
+    /// Global settings, used by every profile without its own settings
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub system_settings: Option<SystemSettings>,
+
+    /// Name of the active profile, whose data lives in `remote_nodes` and `profile_settings`
+    #[serde(default = "default_profile_name")]
+    pub active_profile: String,
+
+    /// Settings of the active profile overriding `system_settings`
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub profile_settings: Option<SystemSettings>,
+
+    /// Nodes of the active profile
+    pub remote_nodes: Vec<StoredNode>,
+
+    /// Subscriptions of the active profile
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub subscriptions: Vec<Subscription>,
+
+    /// The inactive profiles
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub profiles: Vec<Profile>,
+
+    /// `remote_nodes` sealed with the master passphrase, only present while the state is locked
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub encrypted_nodes: Option<EncryptedBlob>,
+
+    /// Set when a master passphrase is in use, `remote_nodes` then get encrypted on every save
+    #[serde(skip)]
+    pub vault: Option<Vault>,
+}
+
+impl Default for AppState {
+    fn default() -> Self {
+        AppState {
+            schema_version: SCHEMA_VERSION,
+            window: WindowState::default(),
+            current_node_id: None,
+            current_selection_path: None,
+            system_settings: None,
+            active_profile: default_profile_name(),
+            profile_settings: None,
+            remote_nodes: Vec::new(),
+            subscriptions: Vec::new(),
+            profiles: Vec::new(),
+            encrypted_nodes: None,
+            vault: None,
+        }
+    }
+}
+
+fn default_profile_name() -> String {
+    DEFAULT_PROFILE_NAME.to_string()
+}
+
+/// A named set of nodes with optional settings of its own, e.g. for staging, production and personal use
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Profile {
+    pub name: String,
+
     #[serde(skip_serializing_if = "Option::is_none", default)]
     pub system_settings: Option<SystemSettings>,
 
-    pub remote_nodes: Vec<OverTlsNode>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub current_node_id: Option<NodeId>,
+
+    #[serde(default)]
+    pub remote_nodes: Vec<StoredNode>,
+
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub subscriptions: Vec<Subscription>,
+}
+
+/// What gets encrypted with the master passphrase: the nodes of every profile and the proxy passwords
+#[derive(Serialize, Deserialize)]
+struct SealedNodes {
+    remote_nodes: Vec<StoredNode>,
+    #[serde(default)]
+    profiles: std::collections::BTreeMap<String, Vec<StoredNode>>,
+    /// `listen_password` of the global settings
+    #[serde(default)]
+    listen_password: Option<String>,
+    /// `listen_password` of the profiles with settings of their own, the active one included, by profile name
+    #[serde(default)]
+    profile_listen_passwords: std::collections::BTreeMap<String, String>,
 }
 
 impl AppState {
     pub fn set_current_path(&mut self, path: &std::path::Path) {
         self.current_selection_path = Some(path.to_path_buf());
     }
+
+    pub fn node(&self, id: NodeId) -> Option<&StoredNode> {
+        self.remote_nodes.iter().find(|n| n.id == id)
+    }
+
+    pub fn node_mut(&mut self, id: NodeId) -> Option<&mut StoredNode> {
+        self.remote_nodes.iter_mut().find(|n| n.id == id)
+    }
+
+    /// Row of the node in `remote_nodes`, which is also its row in the table
+    pub fn node_index(&self, id: NodeId) -> Option<usize> {
+        self.remote_nodes.iter().position(|n| n.id == id)
+    }
+
+    pub fn current_node(&self) -> Option<&StoredNode> {
+        self.current_node_id.and_then(|id| self.node(id))
+    }
+
+    /// Store a new node and return its freshly assigned identifier
+    pub fn add_node(&mut self, node: OverTlsNode) -> NodeId {
+        let stored = StoredNode::new(node);
+        let id = stored.id;
+        self.remote_nodes.push(stored);
+        id
+    }
+
+    /// Add the nodes, handling those with the same identity as a listed or an earlier imported node by `policy`.
+    /// Returns the numbers of new nodes added and of duplicates met.
+    pub fn add_nodes(&mut self, nodes: Vec<OverTlsNode>, policy: DuplicatePolicy) -> (usize, usize) {
+        let (mut added, mut duplicates) = (0, 0);
+        for node in nodes {
+            let Some(index) = self.duplicate_index(&node) else {
+                self.add_node(node);
+                added += 1;
+                continue;
+            };
+            duplicates += 1;
+            match policy {
+                DuplicatePolicy::Skip => {}
+                DuplicatePolicy::Replace => self.remote_nodes[index].node = node,
+                DuplicatePolicy::KeepBoth => {
+                    self.add_node(node);
+                }
+            }
+        }
+        (added, duplicates)
+    }
+
+    /// How many of `nodes` have the same identity as a listed node or one before them
+    pub fn count_duplicates(&self, nodes: &[OverTlsNode]) -> usize {
+        let mut known: Vec<_> = self.remote_nodes.iter().filter_map(|n| NodeIdentity::of(&n.node)).collect();
+        let mut duplicates = 0;
+        for identity in nodes.iter().filter_map(NodeIdentity::of) {
+            if known.contains(&identity) {
+                duplicates += 1;
+            } else {
+                known.push(identity);
+            }
+        }
+        duplicates
+    }
+
+    fn duplicate_index(&self, node: &OverTlsNode) -> Option<usize> {
+        let identity = NodeIdentity::of(node)?;
+        self.remote_nodes
+            .iter()
+            .position(|n| NodeIdentity::of(&n.node).as_ref() == Some(&identity))
+    }
+
+    /// Groups of listed nodes sharing an identity, as indices into `remote_nodes` in list order
+    pub fn duplicate_groups(&self) -> Vec<Vec<usize>> {
+        let mut groups: Vec<(NodeIdentity, Vec<usize>)> = Vec::new();
+        for (index, stored) in self.remote_nodes.iter().enumerate() {
+            let Some(identity) = NodeIdentity::of(&stored.node) else {
+                continue;
+            };
+            match groups.iter_mut().find(|(known, _)| *known == identity) {
+                Some((_, group)) => group.push(index),
+                None => groups.push((identity, vec![index])),
+            }
+        }
+        groups.into_iter().map(|(_, group)| group).filter(|group| group.len() > 1).collect()
+    }
+
+    /// Keep one node of each duplicate group: the first of `preferred` found in the group, otherwise the first one listed.
+    /// Returns the number of nodes removed.
+    pub fn remove_duplicate_nodes(&mut self, preferred: &[NodeId]) -> usize {
+        let mut removed = std::collections::HashSet::new();
+        for group in self.duplicate_groups() {
+            let keep = preferred
+                .iter()
+                .find_map(|id| group.iter().copied().find(|&i| self.remote_nodes[i].id == *id))
+                .unwrap_or(group[0]);
+            removed.extend(group.into_iter().filter(|&i| i != keep));
+        }
+        self.remote_nodes = std::mem::take(&mut self.remote_nodes)
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| !removed.contains(i))
+            .map(|(_, stored)| stored)
+            .collect();
+        if self.current_node_id.is_some_and(|id| self.node(id).is_none()) {
+            self.current_node_id = None;
+        }
+        removed.len()
+    }
+
+    /// The nodes are encrypted and waiting for the master passphrase
+    pub fn is_locked(&self) -> bool {
+        self.encrypted_nodes.is_some()
+    }
+
+    /// Decrypt the nodes with the master passphrase and keep the derived key for the next saves
+    pub fn unlock(&mut self, passphrase: &str) -> std::io::Result<()> {
+        let Some(blob) = &self.encrypted_nodes else {
+            return Ok(());
+        };
+        let (vault, plaintext) = Vault::unlock(passphrase, blob)?;
+        self.open_sealed_nodes(&plaintext)?;
+        self.vault = Some(vault);
+        Ok(())
+    }
+
+    /// Decrypt the nodes with an already derived key, e.g. for a `config.json` reloaded from disk
+    pub fn unlock_with_vault(&mut self, vault: &Vault) -> std::io::Result<()> {
+        let Some(blob) = &self.encrypted_nodes else {
+            return Ok(());
+        };
+        let plaintext = vault.decrypt(blob)?;
+        self.open_sealed_nodes(&plaintext)?;
+        self.vault = Some(vault.clone());
+        Ok(())
+    }
+
+    fn open_sealed_nodes(&mut self, plaintext: &[u8]) -> std::io::Result<()> {
+        let mut sealed: SealedNodes = serde_json::from_slice(plaintext)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Invalid encrypted nodes: {e}")))?;
+        self.remote_nodes = sealed.remote_nodes;
+        for profile in &mut self.profiles {
+            profile.remote_nodes = sealed.profiles.remove(&profile.name).unwrap_or_default();
+        }
+        if let Some(settings) = &mut self.system_settings {
+            settings.listen_password = sealed.listen_password;
+        }
+        let mut passwords = sealed.profile_listen_passwords;
+        if let Some(settings) = &mut self.profile_settings {
+            settings.listen_password = passwords.remove(&self.active_profile);
+        }
+        for profile in &mut self.profiles {
+            if let Some(settings) = &mut profile.system_settings {
+                settings.listen_password = passwords.remove(&profile.name);
+            }
+        }
+        self.encrypted_nodes = None;
+        Ok(())
+    }
+
+    /// Take everything from a state reloaded from disk, except what only makes sense for this session:
+    /// the window geometry, the last used directory, and the master passphrase if the file has none.
+    pub fn reload_from(&mut self, other: AppState) {
+        let window = self.window.clone();
+        let current_selection_path = self.current_selection_path.take();
+        let vault = self.vault.take();
+        *self = other;
+        self.window = window;
+        self.current_selection_path = current_selection_path;
+        if self.vault.is_none() {
+            self.vault = vault;
+        }
+    }
+
+    /// Add the nodes and profiles of `other` missing here, matched by node id and profile name.
+    /// Nodes present on both sides keep their version here. Returns the number of nodes added.
+    pub fn merge_nodes_from(&mut self, other: AppState) -> usize {
+        if self.system_settings.is_none() {
+            self.system_settings = other.system_settings;
+        }
+        let theirs =
+            std::iter::once((other.active_profile, other.remote_nodes)).chain(other.profiles.into_iter().map(|p| (p.name, p.remote_nodes)));
+        let mut added = 0;
+        for (name, nodes) in theirs {
+            let mine = if name == self.active_profile {
+                Some(&mut self.remote_nodes)
+            } else {
+                self.profiles.iter_mut().find(|p| p.name == name).map(|p| &mut p.remote_nodes)
+            };
+            match mine {
+                Some(mine) => {
+                    for node in nodes {
+                        if !mine.iter().any(|n| n.id == node.id) {
+                            mine.push(node);
+                            added += 1;
+                        }
+                    }
+                }
+                None => {
+                    added += nodes.len();
+                    self.profiles.push(Profile {
+                        name,
+                        system_settings: None,
+                        current_node_id: None,
+                        remote_nodes: nodes,
+                        subscriptions: Vec::new(),
+                    });
+                }
+            }
+        }
+        added
+    }
+
+    /// Fallback for a forgotten passphrase: drop the encrypted nodes but keep everything else
+    pub fn discard_encrypted_nodes(&mut self) {
+        self.encrypted_nodes = None;
+        self.vault = None;
+        self.remote_nodes.clear();
+        self.current_node_id = None;
+        for profile in &mut self.profiles {
+            profile.remote_nodes.clear();
+            profile.current_node_id = None;
+        }
+    }
+
+    /// Settings in effect for the active profile
+    pub fn effective_settings(&self) -> SystemSettings {
+        self.profile_settings
+            .clone()
+            .or_else(|| self.system_settings.clone())
+            .unwrap_or_default()
+    }
+
+    /// Store settings edited by the user, into the active profile if it has its own settings
+    pub fn set_effective_settings(&mut self, settings: SystemSettings) {
+        match &mut self.profile_settings {
+            Some(profile_settings) => *profile_settings = settings,
+            None => self.system_settings = Some(settings),
+        }
+    }
+
+    /// Names of all profiles, the active one first
+    pub fn profile_names(&self) -> Vec<String> {
+        std::iter::once(self.active_profile.clone())
+            .chain(self.profiles.iter().map(|p| p.name.clone()))
+            .collect()
+    }
+
+    fn check_new_profile_name(&self, name: &str) -> std::io::Result<()> {
+        use std::io::{Error, ErrorKind::InvalidInput};
+        if name.trim().is_empty() || name.trim() != name {
+            return Err(Error::new(
+                InvalidInput,
+                "Profile name must not be empty or start or end with spaces",
+            ));
+        }
+        // These characters have a special meaning in menu paths
+        if name.contains(['/', '\\', '&', '_']) {
+            return Err(Error::new(InvalidInput, "Profile name must not contain '/', '\\', '&' or '_'"));
+        }
+        if self.profile_names().iter().any(|n| n == name) {
+            return Err(Error::new(
+                std::io::ErrorKind::AlreadyExists,
+                format!("Profile '{name}' already exists"),
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn create_profile(&mut self, name: &str) -> std::io::Result<()> {
+        self.check_new_profile_name(name)?;
+        self.profiles.push(Profile {
+            name: name.to_string(),
+            system_settings: None,
+            current_node_id: None,
+            remote_nodes: Vec::new(),
+            subscriptions: Vec::new(),
+        });
+        Ok(())
+    }
+
+    pub fn rename_profile(&mut self, name: &str, new_name: &str) -> std::io::Result<()> {
+        self.check_new_profile_name(new_name)?;
+        if self.active_profile == name {
+            self.active_profile = new_name.to_string();
+        } else {
+            self.inactive_profile_mut(name)?.name = new_name.to_string();
+        }
+        Ok(())
+    }
+
+    /// Copy a profile under a new name, the copied nodes get new ids
+    pub fn clone_profile(&mut self, name: &str, new_name: &str) -> std::io::Result<()> {
+        self.check_new_profile_name(new_name)?;
+        let mut profile = if self.active_profile == name {
+            Profile {
+                name: name.to_string(),
+                system_settings: self.profile_settings.clone(),
+                current_node_id: self.current_node_id,
+                remote_nodes: self.remote_nodes.clone(),
+                subscriptions: self.subscriptions.clone(),
+            }
+        } else {
+            self.inactive_profile_mut(name)?.clone()
+        };
+        profile.name = new_name.to_string();
+        // Subscriptions get their own ids too, so that refreshes of the copies don't get mixed up
+        let mut subscription_ids = std::collections::HashMap::new();
+        for subscription in &mut profile.subscriptions {
+            let id = SubscriptionId::new_v4();
+            subscription_ids.insert(subscription.id, id);
+            subscription.id = id;
+        }
+        for stored in &mut profile.remote_nodes {
+            let id = NodeId::new_v4();
+            if profile.current_node_id == Some(stored.id) {
+                profile.current_node_id = Some(id);
+            }
+            stored.id = id;
+            stored.subscription_id = stored.subscription_id.and_then(|old| subscription_ids.get(&old).copied());
+        }
+        self.profiles.push(profile);
+        Ok(())
+    }
+
+    /// Delete an inactive profile, the active one can't be deleted
+    pub fn delete_profile(&mut self, name: &str) -> std::io::Result<()> {
+        if self.active_profile == name {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "The active profile can't be deleted",
+            ));
+        }
+        self.inactive_profile_mut(name)?;
+        self.profiles.retain(|p| p.name != name);
+        Ok(())
+    }
+
+    /// Make `name` the active profile, stashing the current one into `profiles`
+    pub fn switch_profile(&mut self, name: &str) -> std::io::Result<()> {
+        if self.active_profile == name {
+            return Ok(());
+        }
+        let index = self
+            .profiles
+            .iter()
+            .position(|p| p.name == name)
+            .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, format!("Profile '{name}' not found")))?;
+        let target = self.profiles.remove(index);
+        let current = Profile {
+            name: std::mem::replace(&mut self.active_profile, target.name),
+            system_settings: std::mem::replace(&mut self.profile_settings, target.system_settings),
+            current_node_id: std::mem::replace(&mut self.current_node_id, target.current_node_id),
+            remote_nodes: std::mem::replace(&mut self.remote_nodes, target.remote_nodes),
+            subscriptions: std::mem::replace(&mut self.subscriptions, target.subscriptions),
+        };
+        self.profiles.insert(index, current);
+        Ok(())
+    }
+
+    fn inactive_profile_mut(&mut self, name: &str) -> std::io::Result<&mut Profile> {
+        self.profiles
+            .iter_mut()
+            .find(|p| p.name == name)
+            .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, format!("Profile '{name}' not found")))
+    }
+
+    /// Remove a node, clearing the selection if it pointed at it
+    pub fn remove_node(&mut self, id: NodeId) -> Option<StoredNode> {
+        let index = self.node_index(id)?;
+        if self.current_node_id == Some(id) {
+            self.current_node_id = None;
+        }
+        Some(self.remote_nodes.remove(index))
+    }
+
+    /// Replace the nodes of subscription `id` of the active profile by freshly fetched ones, see `apply_subscription_nodes_to`
+    pub fn apply_subscription_nodes(&mut self, id: SubscriptionId, nodes: Vec<OverTlsNode>) -> (usize, usize) {
+        apply_subscription_nodes_to(&mut self.remote_nodes, &mut self.current_node_id, id, nodes)
+    }
+
+    /// Nodes, selection and subscriptions of profile `name`, active or not
+    fn profile_parts_mut(&mut self, name: &str) -> Option<(&mut Vec<StoredNode>, &mut Option<NodeId>, &mut Vec<Subscription>)> {
+        if self.active_profile == name {
+            return Some((&mut self.remote_nodes, &mut self.current_node_id, &mut self.subscriptions));
+        }
+        let profile = self.profiles.iter_mut().find(|p| p.name == name)?;
+        Some((&mut profile.remote_nodes, &mut profile.current_node_id, &mut profile.subscriptions))
+    }
+
+    /// Subscription `id` of profile `profile`, active or not
+    pub fn profile_subscription(&self, profile: &str, id: SubscriptionId) -> Option<&Subscription> {
+        let subscriptions = if self.active_profile == profile {
+            &self.subscriptions
+        } else {
+            &self.profiles.iter().find(|p| p.name == profile)?.subscriptions
+        };
+        subscriptions.iter().find(|s| s.id == id)
+    }
+
+    /// Apply the nodes fetched for subscription `id` to the profile the fetch was started for, even if another one is active now.
+    /// Returns the number of nodes added and removed, `None` if the subscription is gone.
+    pub fn apply_subscription_fetch(&mut self, profile: &str, id: SubscriptionId, nodes: Vec<OverTlsNode>) -> Option<(usize, usize)> {
+        let (remote_nodes, current_node_id, subscriptions) = self.profile_parts_mut(profile)?;
+        let subscription = subscriptions.iter_mut().find(|s| s.id == id)?;
+        subscription.last_update = Some(chrono::Utc::now());
+        subscription.last_error = None;
+        Some(apply_subscription_nodes_to(remote_nodes, current_node_id, id, nodes))
+    }
+
+    /// Record why fetching subscription `id` of `profile` failed
+    pub fn set_subscription_error(&mut self, profile: &str, id: SubscriptionId, error: String) {
+        if let Some((_, _, subscriptions)) = self.profile_parts_mut(profile)
+            && let Some(subscription) = subscriptions.iter_mut().find(|s| s.id == id)
+        {
+            subscription.last_error = Some(error);
+        }
+    }
+
+    pub fn subscription_mut(&mut self, id: SubscriptionId) -> Option<&mut Subscription> {
+        self.subscriptions.iter_mut().find(|s| s.id == id)
+    }
+
+    /// Remove subscription `id` together with its nodes
+    pub fn remove_subscription(&mut self, id: SubscriptionId) -> Option<Subscription> {
+        let index = self.subscriptions.iter().position(|s| s.id == id)?;
+        self.apply_subscription_nodes(id, Vec::new());
+        Some(self.subscriptions.remove(index))
+    }
 }
 
-impl Drop for AppState {
-    fn drop(&mut self) {
-        // Bug: `drop` method should not be called at all, we should call `save_app_state` explicitly
-        if let Err(e) = save_app_state(self) {
-            log::debug!("Failed to save app state: {e}");
+/// Replace the nodes of subscription `id` by freshly fetched ones, in place in the node list.
+/// A fetched node equal to an existing one, or else with the same remarks, keeps that node's id,
+/// so the selection and open dialogs survive refreshes. Returns the number of nodes added and removed.
+fn apply_subscription_nodes_to(
+    remote_nodes: &mut Vec<StoredNode>,
+    current_node_id: &mut Option<NodeId>,
+    id: SubscriptionId,
+    nodes: Vec<OverTlsNode>,
+) -> (usize, usize) {
+    let position = remote_nodes.iter().position(|n| n.subscription_id == Some(id));
+    let (mut old, kept): (Vec<StoredNode>, Vec<StoredNode>) = std::mem::take(remote_nodes)
+        .into_iter()
+        .partition(|n| n.subscription_id == Some(id));
+    *remote_nodes = kept;
+
+    let as_json = |node: &OverTlsNode| serde_json::to_value(node).ok();
+    let mut added = 0;
+    let mut fresh = Vec::with_capacity(nodes.len());
+    for node in nodes {
+        let json = as_json(&node);
+        let same = old
+            .iter()
+            .position(|o| as_json(&o.node) == json)
+            .or_else(|| {
+                let identity = NodeIdentity::of(&node)?;
+                old.iter().position(|o| NodeIdentity::of(&o.node).as_ref() == Some(&identity))
+            })
+            .or_else(|| old.iter().position(|o| o.node.remarks.is_some() && o.node.remarks == node.remarks));
+        let id_reused = same.map(|i| old.remove(i).id);
+        if id_reused.is_none() {
+            added += 1;
+        }
+        fresh.push(StoredNode {
+            id: id_reused.unwrap_or_else(NodeId::new_v4),
+            node,
+            subscription_id: Some(id),
+        });
+    }
+    if current_node_id.is_some_and(|current| old.iter().any(|o| o.id == current)) {
+        *current_node_id = None;
+    }
+    let position = position.unwrap_or(remote_nodes.len());
+    remote_nodes.splice(position..position, fresh);
+    (added, old.len())
+}
+
+/// What part of the `AppState` a mutation touched, passed to the `StateStore` listeners
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StateEvent {
+    /// Nodes were added, deleted, edited or imported
+    NodesChanged,
+    /// The selected node changed
+    SelectionChanged,
+    /// The system settings changed
+    SettingsChanged,
+    /// Window geometry, last used directory and other UI details changed
+    UiChanged,
+    /// A node was started or stopped
+    RunningChanged,
+    /// Profiles were created, renamed, deleted, or the active profile changed
+    ProfilesChanged,
+    /// Subscriptions were added, removed, or their refresh status changed
+    SubscriptionsChanged,
+}
+
+/// Delay in seconds between the last mutation and the autosave, so that bursts of changes are saved once
+const AUTOSAVE_DELAY: f64 = 1.0;
+
+type StateListener = Rc<dyn Fn(StateEvent, &AppState)>;
+
+struct StoreInner {
+    state: AppState,
+    /// The node currently running, it's runtime-only and never saved
+    running_node_id: Option<NodeId>,
+    dirty: bool,
+    generation: u64,
+    /// Set while the closure of an `update` runs
+    updating: bool,
+    listeners: Vec<StateListener>,
+    config_path: PathBuf,
+    /// Stamp of `config.json` when we last read or wrote it
+    disk_stamp: DiskStamp,
+    /// Contents of `config.json` when we last read or wrote it, to tell our own writes from external ones
+    disk_contents: Option<String>,
+}
+
+/// Modification time and size of a file, to notice changes cheaply
+type DiskStamp = Option<(std::time::SystemTime, u64)>;
+
+fn disk_stamp(path: &std::path::Path) -> DiskStamp {
+    let metadata = std::fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+/// `config.json` as changed on disk by another program
+pub struct ExternalChange {
+    state: AppState,
+    contents: String,
+}
+
+/// How to combine an external change of `config.json` with the state in memory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExternalChangeResolution {
+    /// Replace the state in memory by the one on disk
+    Reload,
+    /// Keep the state in memory and add the nodes only found on disk
+    Merge,
+    /// Keep the state in memory, it overwrites the file at the next save
+    KeepMine,
+}
+
+/// The single owner of the `AppState`.
+///
+/// Every mutation but the selection goes through `update`, which notifies the listeners and schedules a debounced autosave,
+/// so a crash loses at most the last `AUTOSAVE_DELAY` seconds of edits. Call `flush` before exiting.
+#[derive(Clone)]
+pub struct StateStore {
+    inner: Rc<RefCell<StoreInner>>,
+    /// Parts of split QR codes scanned so far, kept across scans and files until their sequence is complete
+    qr_parts: Rc<RefCell<crate::qr_parts::PartAssembler>>,
+}
+
+impl StateStore {
+    pub fn new(state: AppState) -> Self {
+        Self::with_config_path(state, get_config_path())
+    }
+
+    fn with_config_path(state: AppState, config_path: PathBuf) -> Self {
+        let inner = StoreInner {
+            state,
+            running_node_id: None,
+            dirty: false,
+            generation: 0,
+            updating: false,
+            listeners: Vec::new(),
+            disk_stamp: disk_stamp(&config_path),
+            disk_contents: std::fs::read_to_string(&config_path).ok(),
+            config_path,
+        };
+        StateStore {
+            inner: Rc::new(RefCell::new(inner)),
+            qr_parts: Rc::default(),
+        }
+    }
+
+    /// The parts of split QR codes waiting for the rest of their sequence, don't hold it across an import
+    pub fn qr_parts(&self) -> RefMut<'_, crate::qr_parts::PartAssembler> {
+        self.qr_parts.borrow_mut()
+    }
+
+    /// Read access to the state, don't hold it across a call to `update`
+    pub fn state(&self) -> Ref<'_, AppState> {
+        Ref::map(self.inner.borrow(), |inner| &inner.state)
+    }
+
+    /// Register a listener called after every mutation, it must not mutate the store itself
+    pub fn subscribe(&self, listener: impl Fn(StateEvent, &AppState) + 'static) {
+        self.inner.borrow_mut().listeners.push(Rc::new(listener));
+    }
+
+    /// Mutate the state, then notify the listeners with `event` and schedule an autosave.
+    ///
+    /// `f` works on a copy, so that it can still read the store, which keeps the state from before the update meanwhile.
+    /// It must not update the store itself, such a nested update would be overwritten.
+    pub fn update<R>(&self, event: StateEvent, f: impl FnOnce(&mut AppState) -> R) -> R {
+        let (mut state, nested) = {
+            let mut inner = self.inner.borrow_mut();
+            (inner.state.clone(), std::mem::replace(&mut inner.updating, true))
+        };
+        if nested {
+            log::error!("Store updated from within an update, the change is overwritten");
+        }
+        let result = f(&mut state);
+        {
+            let mut inner = self.inner.borrow_mut();
+            inner.state = state;
+            inner.updating = nested;
+        }
+        self.mark_dirty();
+        self.notify(event);
+        result
+    }
+
+    fn mark_dirty(&self) {
+        {
+            let mut inner = self.inner.borrow_mut();
+            inner.dirty = true;
+            inner.generation += 1;
+        }
+        self.schedule_autosave();
+    }
+
+    /// Call the listeners on a copy of the state, the store isn't borrowed meanwhile so they can use it
+    fn notify(&self, event: StateEvent) {
+        let (listeners, state) = {
+            let inner = self.inner.borrow();
+            if inner.listeners.is_empty() {
+                return;
+            }
+            (inner.listeners.clone(), inner.state.clone())
+        };
+        for listener in listeners {
+            listener(event, &state);
+        }
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.inner.borrow().dirty
+    }
+
+    pub fn running_node_id(&self) -> Option<NodeId> {
+        self.inner.borrow().running_node_id
+    }
+
+    /// Track the running node, this is not a change of the persisted state so nothing gets saved
+    pub fn set_running_node_id(&self, id: Option<NodeId>) {
+        if self.inner.borrow().running_node_id == id {
+            return;
+        }
+        self.inner.borrow_mut().running_node_id = id;
+        self.notify(StateEvent::RunningChanged);
+    }
+
+    /// Change the selected node. It's only worth saving along with a real change or on exit, so no autosave is scheduled.
+    pub fn select_node(&self, id: Option<NodeId>) {
+        if self.inner.borrow().state.current_node_id == id {
+            return;
+        }
+        self.inner.borrow_mut().state.current_node_id = id;
+        self.notify(StateEvent::SelectionChanged);
+    }
+
+    fn schedule_autosave(&self) {
+        let generation = self.inner.borrow().generation;
+        let store = self.clone();
+        // Tests have no event loop, they call `autosave` themselves
+        if cfg!(not(test)) {
+            fltk::app::add_timeout3(AUTOSAVE_DELAY, move |_| store.autosave(generation));
+        }
+    }
+
+    /// Save the state, unless another mutation happened since the one numbered `generation`
+    fn autosave(&self, generation: u64) {
+        // Only the timeout scheduled by the latest mutation saves, earlier ones are superseded
+        if self.inner.borrow().generation != generation {
+            return;
+        }
+        // Let `poll_external_change` deal with it first, instead of clobbering the external change
+        if self.changed_on_disk() {
+            log::debug!("Config file changed on disk, autosave deferred");
+            return;
+        }
+        if let Err(e) = self.flush() {
+            log::error!("Failed to autosave app state: {e}");
+        }
+    }
+
+    /// Save the state now if it has unsaved changes
+    pub fn flush(&self) -> std::io::Result<()> {
+        if !self.inner.borrow().dirty {
+            return Ok(());
+        }
+        let contents = {
+            let inner = self.inner.borrow();
+            save_app_state_to(&inner.config_path, &inner.state)?
+        };
+        let mut inner = self.inner.borrow_mut();
+        inner.dirty = false;
+        inner.disk_stamp = disk_stamp(&inner.config_path);
+        inner.disk_contents = Some(contents);
+        log::trace!("App state saved");
+        Ok(())
+    }
+
+    fn changed_on_disk(&self) -> bool {
+        let inner = self.inner.borrow();
+        disk_stamp(&inner.config_path) != inner.disk_stamp
+    }
+
+    /// Check whether `config.json` was changed by another program since we last read or wrote it.
+    ///
+    /// Each change is reported once: a file that fails to parse is an error now, and ignored afterwards.
+    pub fn poll_external_change(&self) -> std::io::Result<Option<ExternalChange>> {
+        if !self.changed_on_disk() {
+            return Ok(None);
+        }
+        let config_path = {
+            let mut inner = self.inner.borrow_mut();
+            inner.disk_stamp = disk_stamp(&inner.config_path);
+            inner.config_path.clone()
+        };
+        // Reschedule an autosave deferred because of this change, applying the change may supersede it
+        if self.is_dirty() {
+            self.schedule_autosave();
+        }
+        let contents = match std::fs::read_to_string(&config_path) {
+            Ok(contents) => contents,
+            // Deleted, the next save writes it again
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => {
+                return Err(std::io::Error::new(
+                    e.kind(),
+                    format!("Failed to read {}: {e}", config_path.display()),
+                ));
+            }
+        };
+        if self.inner.borrow().disk_contents.as_deref() == Some(contents.as_str()) {
+            return Ok(None);
+        }
+        let mut state = parse_app_state(&contents).map_err(|e| std::io::Error::new(e.kind(), format!("{}: {e}", config_path.display())))?;
+        if state.is_locked() {
+            let vault = self.state().vault.clone().ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::PermissionDenied,
+                    "The nodes on disk are encrypted with a master passphrase, restart the app to unlock them",
+                )
+            })?;
+            state.unlock_with_vault(&vault)?;
+        }
+        Ok(Some(ExternalChange { state, contents }))
+    }
+
+    /// Apply an external change reported by `poll_external_change`
+    pub fn apply_external_change(&self, change: ExternalChange, resolution: ExternalChangeResolution) {
+        self.inner.borrow_mut().disk_contents = Some(change.contents);
+        match resolution {
+            ExternalChangeResolution::Reload => {
+                {
+                    let mut inner = self.inner.borrow_mut();
+                    inner.state.reload_from(change.state);
+                    // The state now matches the file, also cancels a pending autosave
+                    inner.dirty = false;
+                    inner.generation += 1;
+                }
+                self.notify(StateEvent::ProfilesChanged);
+            }
+            ExternalChangeResolution::Merge => {
+                let added = self.update(StateEvent::ProfilesChanged, |s| s.merge_nodes_from(change.state));
+                log::info!("Merged {added} node(s) from the config file changed on disk");
+            }
+            ExternalChangeResolution::KeepMine => self.mark_dirty(),
         }
     }
 }
 
 fn get_real_config_dir() -> PathBuf {
+    // Elevated, `dirs` would resolve to root's config directory
     #[cfg(target_os = "linux")]
-    if let Ok(sudo_user) = std::env::var("SUDO_USER") {
-        let home_path = PathBuf::from("/home").join(&sudo_user).join(".config");
-        return home_path;
+    if let Some(user) = crate::util::real_user() {
+        return user.home.join(".config");
     }
     dirs::config_dir().unwrap_or_else(|| std::env::current_dir().unwrap())
 }
 
+/// Config file chosen on the command line, by environment or by portable mode, see `cli::CliArgs`
+static CONFIG_PATH_OVERRIDE: std::sync::OnceLock<PathBuf> = std::sync::OnceLock::new();
+
+/// Use `path` instead of the default `config.json`, its backups are kept next to it.
+/// Must be called before the state is loaded, later calls are ignored.
+pub fn set_config_path_override(path: PathBuf) {
+    if CONFIG_PATH_OVERRIDE.set(path).is_err() {
+        log::warn!("Config path override is already set");
+    }
+}
+
+/// The config file set by `set_config_path_override`, if any
+pub fn config_path_override() -> Option<PathBuf> {
+    CONFIG_PATH_OVERRIDE.get().cloned()
+}
+
 fn get_config_path() -> PathBuf {
+    if let Some(path) = CONFIG_PATH_OVERRIDE.get() {
+        if let Some(parent) = path.parent() {
+            let _ = std::fs::create_dir_all(parent);
+        }
+        return path.clone();
+    }
     let mut path = get_real_config_dir();
     path.push(env!("CARGO_PKG_NAME"));
-    let _r = std::fs::create_dir_all(&path);
-    #[cfg(target_os = "linux")]
-    if _r.is_ok()
-        && run_as::is_elevated()
-        && let Ok(sudo_user) = std::env::var("SUDO_USER")
-        && path.starts_with(format!("/home/{sudo_user}/.config"))
+    if std::fs::create_dir_all(&path).is_ok()
+        && let Err(e) = crate::util::give_to_real_user(&path, true)
     {
-        // chown -R <sudo_user> <path>
-        let _ = std::process::Command::new("chown").arg("-R").arg(&sudo_user).arg(&path).status();
+        log::warn!("Failed to set the owner of {}: {e}", path.display());
     }
     path.push("config.json");
     path
 }
 
-pub fn load_app_state() -> AppState {
-    let config_path = get_config_path();
-    let state: AppState = std::fs::read_to_string(&config_path)
-        .ok()
-        .and_then(|s| serde_json::from_str(&s).ok())
-        .unwrap_or_default();
-    state
+/// Directory of the config file in use, also where the running instance publishes itself
+pub fn config_dir() -> PathBuf {
+    let path = get_config_path();
+    path.parent().map(PathBuf::from).unwrap_or_else(|| PathBuf::from("."))
+}
+
+/// Maximum number of rotating backups kept in the `backups` directory next to `config.json`
+const MAX_BACKUPS: usize = 10;
+
+/// Minimum time between two backups, so that frequent saves don't rotate out older backups too quickly
+const BACKUP_INTERVAL: std::time::Duration = std::time::Duration::from_secs(15 * 60);
+
+fn get_backups_dir() -> PathBuf {
+    backups_dir_of(&get_config_path())
+}
+
+/// The `backups` directory next to `config_path`
+fn backups_dir_of(config_path: &std::path::Path) -> PathBuf {
+    config_path.with_file_name("backups")
 }
 
-pub fn save_app_state(state: &AppState) -> std::io::Result<()> {
+/// Load the app state from `config.json`.
+///
+/// A missing file yields the default state, but a file that can't be read or parsed is an error,
+/// so that the caller never starts from an empty state and overwrites the user's nodes on exit.
+pub fn load_app_state() -> std::io::Result<AppState> {
+    load_app_state_from(&get_config_path())
+}
+
+fn load_app_state_from(config_path: &std::path::Path) -> std::io::Result<AppState> {
+    let contents = match std::fs::read_to_string(config_path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(AppState::default()),
+        Err(e) => {
+            return Err(std::io::Error::new(
+                e.kind(),
+                format!("Failed to read {}: {e}", config_path.display()),
+            ));
+        }
+    };
+    parse_app_state(&contents).map_err(|e| std::io::Error::new(e.kind(), format!("{}: {e}", config_path.display())))
+}
+
+pub fn parse_app_state(contents: &str) -> std::io::Result<AppState> {
+    let invalid_data = |e| std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Invalid config: {e}"));
+    let mut value: serde_json::Value = serde_json::from_str(contents).map_err(invalid_data)?;
+    migrate_config(&mut value)?;
+    serde_json::from_value(value).map_err(invalid_data)
+}
+
+// ===============================================================================================
+
+/// A migration step upgrades a raw config from one schema version to the next one
+type Migration = fn(&mut serde_json::Map<String, serde_json::Value>) -> std::io::Result<()>;
+
+/// `MIGRATIONS[n]` upgrades a config of schema version `n` to version `n + 1`
+const MIGRATIONS: [Migration; SCHEMA_VERSION as usize] = [migrate_v0_to_v1, migrate_v1_to_v2, migrate_v2_to_v3];
+
+/// Upgrade a raw config to `SCHEMA_VERSION` step by step, before it gets deserialized into `AppState`.
+/// Files without a `schema_version` field predate versioning and are treated as version 0.
+pub fn migrate_config(value: &mut serde_json::Value) -> std::io::Result<()> {
+    use std::io::{Error, ErrorKind::InvalidData};
+    let root = value
+        .as_object_mut()
+        .ok_or_else(|| Error::new(InvalidData, "Config root is not a JSON object"))?;
+    let version = match root.get("schema_version") {
+        None => 0,
+        Some(v) => v
+            .as_u64()
+            .and_then(|v| u32::try_from(v).ok())
+            .ok_or_else(|| Error::new(InvalidData, format!("Invalid schema_version: {v}")))?,
+    };
+    if version > SCHEMA_VERSION {
+        let msg =
+            format!("Config schema version {version} is newer than the supported version {SCHEMA_VERSION}, please upgrade the application");
+        return Err(Error::new(InvalidData, msg));
+    }
+    for (from, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        migration(root).map_err(|e| Error::new(e.kind(), format!("Migration from schema version {from} failed: {e}")))?;
+        root.insert("schema_version".into(), (from as u32 + 1).into());
+    }
+    Ok(())
+}
+
+/// v0 -> v1: log levels were free-form strings, normalize them to the `log::LevelFilter` names
+/// and drop values that can't be parsed, so they fall back to the defaults instead of failing silently.
+fn migrate_v0_to_v1(root: &mut serde_json::Map<String, serde_json::Value>) -> std::io::Result<()> {
+    const LOG_LEVEL_KEYS: [&str; 7] = [
+        "log_level",
+        "rustls_log_level",
+        "tokio_tungstenite_log_level",
+        "tungstenite_log_level",
+        "ipstack_log_level",
+        "overtls_log_level",
+        "tun2proxy_log_level",
+    ];
+    let Some(settings) = root.get_mut("system_settings").and_then(|s| s.as_object_mut()) else {
+        return Ok(());
+    };
+    for key in LOG_LEVEL_KEYS {
+        let Some(level) = settings.get(key) else {
+            continue;
+        };
+        let normalized = level
+            .as_str()
+            .and_then(|level| level.trim().parse::<log::LevelFilter>().ok())
+            .map(|filter| format!("{filter:?}"));
+        match normalized {
+            Some(normalized) => settings.insert(key.into(), normalized.into()),
+            None => settings.remove(key),
+        };
+    }
+    Ok(())
+}
+
+/// v1 -> v2: every node is wrapped into a `StoredNode` with a fresh id,
+/// and `current_node_index` is replaced by the id of the node at that row.
+fn migrate_v1_to_v2(root: &mut serde_json::Map<String, serde_json::Value>) -> std::io::Result<()> {
+    use serde_json::Value;
+    let nodes = match root.remove("remote_nodes") {
+        None => Vec::new(),
+        Some(Value::Array(nodes)) => nodes,
+        Some(_) => return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "remote_nodes is not an array")),
+    };
+    let nodes: Vec<Value> = nodes
+        .into_iter()
+        .map(|node| serde_json::json!({ "id": NodeId::new_v4().to_string(), "node": node }))
+        .collect();
+    if let Some(index) = root.remove("current_node_index")
+        && let Some(node) = index.as_u64().and_then(|i| nodes.get(i as usize))
+    {
+        root.insert("current_node_id".into(), node["id"].clone());
+    }
+    root.insert("remote_nodes".into(), Value::Array(nodes));
+    Ok(())
+}
+
+/// v2 -> v3: introduces profiles, the existing nodes and settings become the active default profile.
+/// Older builds would silently drop the inactive profiles, hence the version bump.
+fn migrate_v2_to_v3(root: &mut serde_json::Map<String, serde_json::Value>) -> std::io::Result<()> {
+    root.entry("active_profile").or_insert_with(|| DEFAULT_PROFILE_NAME.into());
+    Ok(())
+}
+
+// ===============================================================================================
+
+/// Name prefix of unreadable configs moved aside by `restore_newest_valid_backup`
+const CORRUPT_CONFIG_PREFIX: &str = "config.json.corrupt-";
+
+/// List the backups of `config.json`, newest first
+pub fn list_backups() -> Vec<PathBuf> {
+    list_backups_in(&get_backups_dir())
+}
+
+fn list_backups_in(backups_dir: &std::path::Path) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(backups_dir) else {
+        return Vec::new();
+    };
+    let mut backups: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            let name = path.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+            name.starts_with("config-") && name.ends_with(".json")
+        })
+        .collect();
+    // File names embed a sortable timestamp
+    backups.sort_by(|a, b| b.file_name().cmp(&a.file_name()));
+    backups
+}
+
+/// Restore the newest backup that can be parsed.
+///
+/// The unreadable `config.json` is moved aside rather than deleted, and the restored backup is written in its place.
+/// Returns the restored state together with the backup it came from.
+pub fn restore_newest_valid_backup() -> std::io::Result<(AppState, PathBuf)> {
+    restore_newest_valid_backup_of(&get_config_path())
+}
+
+fn restore_newest_valid_backup_of(config_path: &std::path::Path) -> std::io::Result<(AppState, PathBuf)> {
+    for backup in list_backups_in(&backups_dir_of(config_path)) {
+        let Ok(contents) = std::fs::read_to_string(&backup) else {
+            continue;
+        };
+        match parse_app_state(&contents) {
+            Ok(state) => {
+                if config_path.exists() {
+                    let ts = chrono::Local::now().format("%Y%m%d-%H%M%S");
+                    let corrupt_path = config_path.with_file_name(format!("{CORRUPT_CONFIG_PREFIX}{ts}"));
+                    std::fs::rename(config_path, &corrupt_path)?;
+                    log::warn!("Moved unreadable config to {}", corrupt_path.display());
+                }
+                write_file_atomically(config_path, contents.as_bytes())?;
+                set_file_owner_if_needed(config_path);
+                return Ok((state, backup));
+            }
+            Err(e) => log::warn!("Skipping invalid backup {}: {e}", backup.display()),
+        }
+    }
+    Err(std::io::Error::new(std::io::ErrorKind::NotFound, "No valid backup found"))
+}
+
+/// Write the state to `config_path`, backing up the file it replaces, returns the written contents
+fn save_app_state_to(config_path: &std::path::Path, state: &AppState) -> std::io::Result<String> {
+    let contents = serialize_app_state(state)?;
+    if let Err(e) = backup_config_if_needed(config_path, &contents, state.vault.as_ref()) {
+        log::warn!("Failed to back up {}: {e}", config_path.display());
+    }
+    write_file_atomically(config_path, contents.as_bytes())?;
+    set_file_owner_if_needed(config_path);
+    Ok(contents)
+}
+
+/// Serialize the state as written to `config.json`, with the nodes encrypted if a master passphrase is set
+pub fn serialize_app_state(state: &AppState) -> std::io::Result<String> {
+    let to_json = |state: &AppState| {
+        serde_json::to_string_pretty(state).map_err(|e| std::io::Error::other(format!("Failed to serialize state: {e}")))
+    };
+    let Some(vault) = &state.vault else {
+        return to_json(state);
+    };
+    let profile_settings = std::iter::once((&state.active_profile, &state.profile_settings))
+        .chain(state.profiles.iter().map(|p| (&p.name, &p.system_settings)));
+    let nodes = SealedNodes {
+        remote_nodes: state.remote_nodes.clone(),
+        profiles: state.profiles.iter().map(|p| (p.name.clone(), p.remote_nodes.clone())).collect(),
+        listen_password: state.system_settings.as_ref().and_then(|s| s.listen_password.clone()),
+        profile_listen_passwords: profile_settings
+            .filter_map(|(name, settings)| Some((name.clone(), settings.as_ref()?.listen_password.clone()?)))
+            .collect(),
+    };
+    let plaintext = serde_json::to_vec(&nodes).map_err(|e| std::io::Error::other(format!("Failed to serialize nodes: {e}")))?;
+    let mut sealed = state.clone();
+    sealed.remote_nodes.clear();
+    sealed.profiles.iter_mut().for_each(|p| p.remote_nodes.clear());
+    let settings = sealed
+        .system_settings
+        .iter_mut()
+        .chain(sealed.profile_settings.iter_mut())
+        .chain(sealed.profiles.iter_mut().filter_map(|p| p.system_settings.as_mut()));
+    settings.for_each(|s| s.listen_password = None);
+    sealed.encrypted_nodes = Some(vault.encrypt(&plaintext)?);
+    to_json(&sealed)
+}
+
+/// Contents of a backup of `old_contents`, sealed like the state being saved: a config saved before
+/// the master passphrase was set is encrypted now, one that is already encrypted is kept as it is.
+fn backup_contents(old_contents: &str, vault: Option<&Vault>) -> std::io::Result<String> {
+    let mut old = parse_app_state(old_contents)?;
+    if old.is_locked() {
+        return Ok(old_contents.to_string());
+    }
+    old.vault = vault.cloned();
+    serialize_app_state(&old)
+}
+
+/// Seal every backup with the new master passphrase, after it was set or changed.
+/// Backups that can't be decrypted with the previous passphrase and unreadable configs moved aside can't be sealed,
+/// they are deleted so that no plaintext copy is left behind.
+pub fn reseal_backups(previous: Option<&Vault>, vault: &Vault) -> std::io::Result<()> {
+    for backup in list_backups() {
+        let state = std::fs::read_to_string(&backup).and_then(|contents| {
+            let mut state = parse_app_state(&contents)?;
+            match previous {
+                Some(previous) if state.is_locked() => state.unlock_with_vault(previous)?,
+                None if state.is_locked() => return Err(std::io::Error::other("Encrypted with an unknown passphrase")),
+                _ => {}
+            }
+            Ok(state)
+        });
+        match state {
+            Ok(mut state) => {
+                state.vault = Some(vault.clone());
+                write_file_atomically(&backup, serialize_app_state(&state)?.as_bytes())?;
+            }
+            Err(e) => {
+                log::warn!("Deleting backup {} that can't be sealed: {e}", backup.display());
+                std::fs::remove_file(&backup)?;
+            }
+        }
+    }
     let config_path = get_config_path();
-    let contents = serde_json::to_string_pretty(state).map_err(|e| std::io::Error::other(format!("Failed to serialize state: {e}")))?;
-    std::fs::write(&config_path, &contents)?;
-    set_file_owner_if_needed(&config_path);
+    let config_dir = config_path.parent().map(PathBuf::from).unwrap_or_default();
+    for entry in std::fs::read_dir(&config_dir)?.filter_map(Result::ok) {
+        if entry.file_name().to_string_lossy().starts_with(CORRUPT_CONFIG_PREFIX) {
+            log::warn!("Deleting unreadable config {}", entry.path().display());
+            std::fs::remove_file(entry.path())?;
+        }
+    }
     Ok(())
 }
 
-/// If needed, set the specified file to be owned by sudo_user
-pub fn set_file_owner_if_needed<P: AsRef<std::path::Path>>(_path: P) {
-    #[cfg(target_os = "linux")]
-    if run_as::is_elevated()
-        && let Ok(sudo_user) = std::env::var("SUDO_USER")
+/// Copy the current `config.json` into the backups directory before it gets replaced by `new_contents`,
+/// keeping at most `MAX_BACKUPS` of them.
+fn backup_config_if_needed(config_path: &std::path::Path, new_contents: &str, vault: Option<&Vault>) -> std::io::Result<()> {
+    let Ok(old_contents) = std::fs::read_to_string(config_path) else {
+        return Ok(());
+    };
+    // Nothing changed, or the old file is not worth keeping
+    if old_contents == new_contents || parse_app_state(&old_contents).is_err() {
+        return Ok(());
+    }
+    let backups_dir = backups_dir_of(config_path);
+    let backups = list_backups_in(&backups_dir);
+    if let Some(newest) = backups.first()
+        && let Ok(modified) = std::fs::metadata(newest).and_then(|m| m.modified())
+        && modified.elapsed().is_ok_and(|elapsed| elapsed < BACKUP_INTERVAL)
     {
-        let _ = std::process::Command::new("chown").arg(&sudo_user).arg(_path.as_ref()).status();
+        return Ok(());
+    }
+    backup_config(&backup_contents(&old_contents, vault)?, &backups_dir, &backups)
+}
+
+/// Back up the current `config.json` right away, regardless of `BACKUP_INTERVAL`, sealed with `vault` if it isn't yet
+pub fn backup_config_now(vault: Option<&Vault>) -> std::io::Result<()> {
+    let contents = std::fs::read_to_string(get_config_path())?;
+    let backups_dir = get_backups_dir();
+    backup_config(&backup_contents(&contents, vault)?, &backups_dir, &list_backups_in(&backups_dir))
+}
+
+/// Write a new backup into `backups_dir`, then prune the oldest of `backups` so that `MAX_BACKUPS` are left
+fn backup_config(contents: &str, backups_dir: &std::path::Path, backups: &[PathBuf]) -> std::io::Result<()> {
+    std::fs::create_dir_all(backups_dir)?;
+    let ts = chrono::Local::now().format("%Y%m%d-%H%M%S%.3f");
+    let backup_path = backups_dir.join(format!("config-{ts}.json"));
+    write_file_atomically(&backup_path, contents.as_bytes())?;
+    set_file_owner_if_needed(backups_dir);
+    set_file_owner_if_needed(&backup_path);
+    log::debug!("Config backed up to {}", backup_path.display());
+
+    for stale in backups.iter().skip(MAX_BACKUPS - 1) {
+        if let Err(e) = std::fs::remove_file(stale) {
+            log::warn!("Failed to remove old backup {}: {e}", stale.display());
+        }
+    }
+    Ok(())
+}
+
+/// Write `contents` to a temporary file next to `path`, fsync it, then rename it over `path`,
+/// so that a crash in the middle of a save never leaves a truncated file behind.
+/// The file is only readable by its owner, it holds credentials.
+pub fn write_file_atomically(path: &std::path::Path, contents: &[u8]) -> std::io::Result<()> {
+    use std::io::Write;
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("Invalid file path: {}", path.display())))?;
+    // Unique, so that concurrent writers don't share a temporary file
+    let tmp_path = path.with_file_name(format!(
+        ".{}.{}.{}.tmp",
+        file_name.to_string_lossy(),
+        std::process::id(),
+        uuid::Uuid::new_v4().simple()
+    ));
+    let write_tmp = || -> std::io::Result<()> {
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = options.open(&tmp_path)?;
+        file.write_all(contents)?;
+        file.sync_all()
+    };
+    if let Err(e) = write_tmp().and_then(|_| std::fs::rename(&tmp_path, path)) {
+        let _ = std::fs::remove_file(&tmp_path);
+        return Err(e);
+    }
+    // Persist the rename itself
+    #[cfg(unix)]
+    if let Some(dir) = path.parent()
+        && let Ok(dir) = std::fs::File::open(dir)
+    {
+        let _ = dir.sync_all();
+    }
+    Ok(())
+}
+
+/// If needed, set the specified file to be owned by sudo_user
+pub fn set_file_owner_if_needed<P: AsRef<std::path::Path>>(path: P) {
+    if let Err(e) = crate::util::give_to_real_user(&path, false) {
+        log::warn!("Failed to set the owner of {}: {e}", path.as_ref().display());
     }
 }
 
@@ -257,3 +1572,429 @@ impl SystemSettings {
             && self.log_level == other.log_level
     }
 }
+
+/// Test data read from `tests/fixtures`, shared by the tests of all modules
+#[cfg(test)]
+pub(crate) mod test_fixtures {
+    use super::*;
+
+    pub fn fixture(name: &str) -> serde_json::Value {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(name);
+        let contents = std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("Failed to read {}: {e}", path.display()));
+        serde_json::from_str(&contents).unwrap()
+    }
+
+    /// The state of the current schema fixture
+    pub fn fixture_state() -> AppState {
+        parse_app_state(&fixture("config_v3.json").to_string()).unwrap()
+    }
+
+    /// The first node of the current schema fixture
+    pub fn fixture_node() -> OverTlsNode {
+        fixture_state().remote_nodes.remove(0).node
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_fixtures::{fixture, fixture_state};
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("overtls-gui-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Apply the single migration step upgrading `value` from schema version `from`
+    fn migrate_step(from: u32, value: &mut serde_json::Value) {
+        let root = value.as_object_mut().unwrap();
+        MIGRATIONS[from as usize](root).unwrap();
+        root.insert("schema_version".into(), (from + 1).into());
+    }
+
+    #[test]
+    fn migrate_v0_to_v1() {
+        let mut value = fixture("config_v0.json");
+        migrate_step(0, &mut value);
+        assert_eq!(value, fixture("config_v1.json"));
+    }
+
+    #[test]
+    fn migrate_v1_to_v2() {
+        let original = fixture("config_v1.json");
+        let mut value = original.clone();
+        migrate_step(1, &mut value);
+
+        let nodes = value["remote_nodes"].as_array().unwrap();
+        assert_eq!(nodes.len(), original["remote_nodes"].as_array().unwrap().len());
+        for (stored, node) in nodes.iter().zip(original["remote_nodes"].as_array().unwrap()) {
+            assert!(stored["id"].as_str().unwrap().parse::<NodeId>().is_ok());
+            assert_eq!(&stored["node"], node);
+        }
+        // `current_node_index` was 0
+        assert_eq!(value["current_node_id"], nodes[0]["id"]);
+
+        // Ids are random, take them over before comparing the rest with the fixture
+        let mut expected = fixture("config_v2.json");
+        expected["current_node_id"] = value["current_node_id"].clone();
+        for (i, stored) in nodes.iter().enumerate() {
+            expected["remote_nodes"][i]["id"] = stored["id"].clone();
+        }
+        assert_eq!(value, expected);
+    }
+
+    #[test]
+    fn migrate_v2_to_v3() {
+        let mut value = fixture("config_v2.json");
+        migrate_step(2, &mut value);
+        assert_eq!(value, fixture("config_v3.json"));
+    }
+
+    #[test]
+    fn migrate_config_reaches_current_version() {
+        let mut value = fixture("config_v0.json");
+        migrate_config(&mut value).unwrap();
+        assert_eq!(value["schema_version"], SCHEMA_VERSION);
+        assert!(parse_app_state(&value.to_string()).is_ok());
+    }
+
+    #[test]
+    fn migrate_config_keeps_current_version_untouched() {
+        let mut value = fixture(&format!("config_v{SCHEMA_VERSION}.json"));
+        migrate_config(&mut value).unwrap();
+        assert_eq!(value, fixture(&format!("config_v{SCHEMA_VERSION}.json")));
+    }
+
+    #[test]
+    fn migrate_config_rejects_newer_version() {
+        let mut value = fixture(&format!("config_v{SCHEMA_VERSION}.json"));
+        value["schema_version"] = (SCHEMA_VERSION + 1).into();
+        assert!(migrate_config(&mut value).is_err());
+    }
+
+    #[test]
+    fn master_passphrase_seals_nodes_and_listen_passwords() {
+        let plain = fixture("config_v3.json").to_string();
+        let mut state = parse_app_state(&plain).unwrap();
+        state.system_settings.as_mut().unwrap().listen_password = Some("global-secret".into());
+        state.clone_profile(DEFAULT_PROFILE_NAME, "Staging").unwrap();
+        state.profiles[0].system_settings = Some(SystemSettings {
+            listen_password: Some("staging-secret".into()),
+            ..SystemSettings::default()
+        });
+        let vault = Vault::new("passphrase").unwrap();
+        state.vault = Some(vault.clone());
+
+        let contents = serialize_app_state(&state).unwrap();
+        for secret in ["global-secret", "staging-secret", "123.45.67.89"] {
+            assert!(!contents.contains(secret), "{secret} stored in the clear");
+        }
+        let mut reloaded = parse_app_state(&contents).unwrap();
+        reloaded.unlock_with_vault(&vault).unwrap();
+        let password = |settings: &Option<SystemSettings>| settings.as_ref().and_then(|s| s.listen_password.clone());
+        assert_eq!(password(&reloaded.system_settings).as_deref(), Some("global-secret"));
+        assert_eq!(password(&reloaded.profiles[0].system_settings).as_deref(), Some("staging-secret"));
+        assert_eq!(reloaded.remote_nodes.len(), 1);
+
+        // A config saved before the passphrase was set is only ever backed up sealed
+        let backup = backup_contents(&plain, Some(&vault)).unwrap();
+        assert!(!backup.contains("123.45.67.89"));
+        assert_eq!(backup_contents(&contents, None).unwrap(), contents);
+    }
+
+    #[test]
+    fn merge_nodes_from_adds_missing_nodes_and_profiles() {
+        let contents = fixture("config_v3.json").to_string();
+        let mut mine = parse_app_state(&contents).unwrap();
+        mine.remote_nodes[0].node.remarks = Some("Mine".into());
+
+        let mut theirs = parse_app_state(&contents).unwrap();
+        theirs.add_node(theirs.remote_nodes[0].node.clone());
+        theirs.clone_profile(DEFAULT_PROFILE_NAME, "Staging").unwrap();
+
+        assert_eq!(mine.merge_nodes_from(theirs), 3);
+        assert_eq!(mine.remote_nodes.len(), 2);
+        assert_eq!(mine.remote_nodes[0].node.remarks.as_deref(), Some("Mine"));
+        assert_eq!(mine.profiles.len(), 1);
+        assert_eq!(mine.profiles[0].name, "Staging");
+        assert_eq!(mine.profiles[0].remote_nodes.len(), 2);
+    }
+
+    #[test]
+    fn node_identity_ignores_formatting() {
+        let state = fixture_state();
+        let node = state.remote_nodes[0].node.clone();
+        let mut reformatted = node.clone();
+        reformatted.remarks = Some("Other name".into());
+        let client = reformatted.client.as_mut().unwrap();
+        client.server_host = format!("{}.", client.server_host.to_uppercase());
+        assert_eq!(NodeIdentity::of(&node), NodeIdentity::of(&reformatted));
+
+        let mut other_port = node.clone();
+        other_port.client.as_mut().unwrap().server_port += 1;
+        assert_ne!(NodeIdentity::of(&node), NodeIdentity::of(&other_port));
+    }
+
+    #[test]
+    fn add_nodes_handles_duplicates_by_policy() {
+        let mut state = fixture_state();
+        let id = state.remote_nodes[0].id;
+        let mut duplicate = state.remote_nodes[0].node.clone();
+        duplicate.remarks = Some("Imported".into());
+        let mut new_node = duplicate.clone();
+        new_node.client.as_mut().unwrap().server_port += 1;
+        let batch = vec![duplicate, new_node.clone(), new_node];
+        assert_eq!(state.count_duplicates(&batch), 2);
+
+        let mut skip = state.clone();
+        assert_eq!(skip.add_nodes(batch.clone(), DuplicatePolicy::Skip), (1, 2));
+        assert_eq!(skip.remote_nodes.len(), 2);
+        assert_eq!(skip.remote_nodes[0].node.remarks.as_deref(), Some("Example"));
+
+        let mut replace = state.clone();
+        assert_eq!(replace.add_nodes(batch.clone(), DuplicatePolicy::Replace), (1, 2));
+        assert_eq!(replace.remote_nodes.len(), 2);
+        assert_eq!(replace.remote_nodes[0].id, id);
+        assert_eq!(replace.remote_nodes[0].node.remarks.as_deref(), Some("Imported"));
+
+        assert_eq!(state.add_nodes(batch, DuplicatePolicy::KeepBoth), (1, 2));
+        assert_eq!(state.remote_nodes.len(), 4);
+    }
+
+    #[test]
+    fn remove_duplicate_nodes_keeps_the_preferred_node() {
+        let mut state = fixture_state();
+        let node = state.remote_nodes[0].node.clone();
+        let current = state.add_node(node.clone());
+        state.add_node(node);
+        state.current_node_id = Some(current);
+        assert_eq!(state.duplicate_groups(), vec![vec![0, 1, 2]]);
+
+        assert_eq!(state.remove_duplicate_nodes(&[current]), 2);
+        assert_eq!(state.remote_nodes.len(), 1);
+        assert_eq!(state.remote_nodes[0].id, current);
+        assert!(state.duplicate_groups().is_empty());
+    }
+
+    #[test]
+    fn apply_subscription_nodes_keeps_ids_of_unchanged_nodes() {
+        let mut state = fixture_state();
+        let node = state.remote_nodes[0].node.clone();
+        let mut renamed = node.clone();
+        renamed.remarks = Some("Renamed".into());
+        let subscription = SubscriptionId::new_v4();
+
+        assert_eq!(
+            state.apply_subscription_nodes(subscription, vec![node.clone(), renamed.clone()]),
+            (2, 0)
+        );
+        assert_eq!(state.remote_nodes.len(), 3);
+        let kept_id = state.remote_nodes[1].id;
+        state.current_node_id = Some(state.remote_nodes[2].id);
+
+        assert_eq!(state.apply_subscription_nodes(subscription, vec![node]), (0, 1));
+        assert_eq!(state.remote_nodes.len(), 2);
+        assert_eq!(state.remote_nodes[1].id, kept_id);
+        assert_eq!(state.remote_nodes[1].subscription_id, Some(subscription));
+        assert_eq!(state.current_node_id, None);
+    }
+
+    #[test]
+    fn subscription_fetch_applies_to_the_profile_it_was_started_for() {
+        let mut state = fixture_state();
+        let node = state.remote_nodes[0].node.clone();
+        let subscription = crate::subscriptions::Subscription::new("sub", "http://127.0.0.1/", 30);
+        let id = subscription.id;
+        state.subscriptions.push(subscription);
+        state.apply_subscription_nodes(id, vec![node.clone()]);
+        let active = state.active_profile.clone();
+
+        state.clone_profile(&active, "copy").unwrap();
+        let copy = state.profiles.iter().find(|p| p.name == "copy").unwrap();
+        let copy_id = copy.subscriptions[0].id;
+        assert_ne!(copy_id, id);
+        assert_eq!(copy.remote_nodes.last().unwrap().subscription_id, Some(copy_id));
+
+        state.switch_profile("copy").unwrap();
+        let mut renamed = node.clone();
+        renamed.remarks = Some("Renamed".into());
+        assert_eq!(state.apply_subscription_fetch(&active, id, vec![node, renamed]), Some((1, 0)));
+        assert!(state.profile_subscription(&active, id).unwrap().last_update.is_some());
+        assert_eq!(state.remote_nodes.len(), 2);
+        assert_eq!(state.profiles.iter().find(|p| p.name == active).unwrap().remote_nodes.len(), 3);
+        assert_eq!(state.apply_subscription_fetch("copy", id, Vec::new()), None);
+    }
+
+    #[test]
+    fn atomic_write_leaves_a_single_private_file() {
+        let dir = temp_dir("atomic-write");
+        let path = dir.join("config.json");
+        std::fs::write(&path, "old").unwrap();
+        write_file_atomically(&path, b"new").unwrap();
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "new");
+        let names: Vec<_> = std::fs::read_dir(&dir).unwrap().map(|e| e.unwrap().file_name()).collect();
+        assert_eq!(names, ["config.json"]);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn backup_rotation_keeps_max_backups() {
+        let dir = temp_dir("backup-rotation");
+        let backups_dir = dir.join("backups");
+        std::fs::create_dir_all(&backups_dir).unwrap();
+        for i in 0..MAX_BACKUPS + 2 {
+            std::fs::write(backups_dir.join(format!("config-20000101-0000{i:02}.000.json")), "{}").unwrap();
+        }
+        let old = list_backups_in(&backups_dir);
+
+        backup_config("{}", &backups_dir, &old).unwrap();
+        let kept = list_backups_in(&backups_dir);
+        assert_eq!(kept.len(), MAX_BACKUPS);
+        assert!(!old.contains(&kept[0]));
+        assert_eq!(kept[1..], old[..MAX_BACKUPS - 1]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn save_backs_up_the_replaced_config() {
+        let dir = temp_dir("save-backup");
+        let config_path = dir.join("config.json");
+        let mut state = fixture_state();
+        save_app_state_to(&config_path, &state).unwrap();
+        assert!(list_backups_in(&backups_dir_of(&config_path)).is_empty());
+
+        state.remote_nodes.clear();
+        save_app_state_to(&config_path, &state).unwrap();
+        let backups = list_backups_in(&backups_dir_of(&config_path));
+        assert_eq!(backups.len(), 1);
+        let backed_up = parse_app_state(&std::fs::read_to_string(&backups[0]).unwrap()).unwrap();
+        assert_eq!(backed_up.remote_nodes.len(), fixture_state().remote_nodes.len());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn corrupt_config_falls_back_to_the_newest_valid_backup() {
+        let dir = temp_dir("restore-backup");
+        let config_path = dir.join("config.json");
+        let backups_dir = backups_dir_of(&config_path);
+        std::fs::create_dir_all(&backups_dir).unwrap();
+        let valid = fixture("config_v3.json").to_string();
+        let valid_backup = backups_dir.join("config-20000101-000000.000.json");
+        std::fs::write(&valid_backup, &valid).unwrap();
+        std::fs::write(backups_dir.join("config-20000101-000001.000.json"), "{\"remote_nodes\": [").unwrap();
+        // Truncated by a crash in the middle of a save
+        std::fs::write(&config_path, &valid[..valid.len() / 2]).unwrap();
+        assert!(load_app_state_from(&config_path).is_err());
+
+        let (state, backup) = restore_newest_valid_backup_of(&config_path).unwrap();
+        assert_eq!(backup, valid_backup);
+        assert_eq!(state.remote_nodes.len(), fixture_state().remote_nodes.len());
+        assert!(load_app_state_from(&config_path).is_ok());
+        let moved_aside = std::fs::read_dir(&dir)
+            .unwrap()
+            .any(|e| e.unwrap().file_name().to_string_lossy().starts_with(CORRUPT_CONFIG_PREFIX));
+        assert!(moved_aside);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn store_autosaves_the_latest_generation_only() {
+        let dir = temp_dir("store-autosave");
+        let path = dir.join("config.json");
+        let store = StateStore::with_config_path(fixture_state(), path.clone());
+
+        store.update(StateEvent::NodesChanged, |s| s.remote_nodes.clear());
+        let stale = store.inner.borrow().generation;
+        store.update(StateEvent::UiChanged, |_| ());
+        let latest = store.inner.borrow().generation;
+        assert!(store.is_dirty());
+        store.autosave(stale);
+        assert!(!path.exists() && store.is_dirty());
+        store.autosave(latest);
+        assert!(path.exists() && !store.is_dirty());
+        assert!(load_app_state_from(&path).unwrap().remote_nodes.is_empty());
+
+        // Nothing left to save
+        std::fs::remove_file(&path).unwrap();
+        store.flush().unwrap();
+        assert!(!path.exists());
+        // The selection alone isn't worth a save
+        store.select_node(Some(NodeId::new_v4()));
+        assert!(!store.is_dirty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn store_can_be_read_while_updating_and_notifying() {
+        let dir = temp_dir("store-reentrancy");
+        let store = StateStore::with_config_path(fixture_state(), dir.join("config.json"));
+        let nodes = store.state().remote_nodes.len();
+        let notified = Rc::new(std::cell::Cell::new(0));
+        let (store_listener, notified_listener) = (store.clone(), notified.clone());
+        store.subscribe(move |_, state| {
+            assert_eq!(store_listener.state().remote_nodes.len(), state.remote_nodes.len());
+            notified_listener.set(notified_listener.get() + 1);
+        });
+
+        let seen = store.update(StateEvent::NodesChanged, |s| {
+            s.remote_nodes.clear();
+            assert_eq!(store.running_node_id(), None);
+            store.state().remote_nodes.len()
+        });
+        assert_eq!(seen, nodes);
+        assert!(store.state().remote_nodes.is_empty());
+        assert_eq!(notified.get(), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn external_changes_are_picked_up_but_not_our_own_writes() {
+        let dir = temp_dir("hot-reload");
+        let path = dir.join("config.json");
+        let store = StateStore::with_config_path(fixture_state(), path.clone());
+        store.update(StateEvent::UiChanged, |_| ());
+        store.flush().unwrap();
+        assert!(store.poll_external_change().unwrap().is_none());
+
+        // Our own write again
+        store.update(StateEvent::NodesChanged, |s| s.remote_nodes.truncate(1));
+        store.flush().unwrap();
+        assert!(store.poll_external_change().unwrap().is_none());
+
+        // Another program empties the node list
+        let mut external = fixture_state();
+        external.remote_nodes.clear();
+        std::fs::write(&path, serialize_app_state(&external).unwrap()).unwrap();
+        let change = store.poll_external_change().unwrap().unwrap();
+        assert!(store.poll_external_change().unwrap().is_none());
+        store.apply_external_change(change, ExternalChangeResolution::Reload);
+        assert!(store.state().remote_nodes.is_empty());
+        assert!(!store.is_dirty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn invalid_external_change_keeps_the_state_in_memory() {
+        let dir = temp_dir("hot-reload-invalid");
+        let path = dir.join("config.json");
+        let store = StateStore::with_config_path(fixture_state(), path.clone());
+        store.update(StateEvent::UiChanged, |_| ());
+        store.flush().unwrap();
+        let nodes = store.state().remote_nodes.len();
+
+        std::fs::write(&path, "{ truncated").unwrap();
+        assert_eq!(store.poll_external_change().unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+        // Reported once
+        assert!(store.poll_external_change().unwrap().is_none());
+        assert_eq!(store.state().remote_nodes.len(), nodes);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,204 @@
+use crate::OverTlsNode;
+use base64::{
+    Engine,
+    engine::general_purpose::{STANDARD_NO_PAD, URL_SAFE_NO_PAD},
+};
+use serde::{Deserialize, Serialize};
+
+/// Persistent identifier of a subscription, its nodes carry it in `StoredNode::subscription_id`
+pub type SubscriptionId = uuid::Uuid;
+
+pub const DEFAULT_REFRESH_INTERVAL_MINUTES: u32 = 60;
+
+const FETCH_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// An HTTP(S) URL publishing a base64 encoded list of `ssr://` or `overtls://` links, one per line
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Subscription {
+    pub id: SubscriptionId,
+    pub name: String,
+    pub url: String,
+
+    /// Minutes between two scheduled refreshes, 0 to only refresh manually
+    pub refresh_interval_minutes: u32,
+
+    /// Last successful refresh
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub last_update: Option<chrono::DateTime<chrono::Utc>>,
+
+    /// Last refresh started, successful or not, so failing subscriptions aren't retried in a tight loop
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub last_attempt: Option<chrono::DateTime<chrono::Utc>>,
+
+    /// Why the last refresh failed, cleared by the next successful one
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub last_error: Option<String>,
+}
+
+impl Subscription {
+    pub fn new(name: &str, url: &str, refresh_interval_minutes: u32) -> Self {
+        Subscription {
+            id: SubscriptionId::new_v4(),
+            name: name.to_string(),
+            url: url.to_string(),
+            refresh_interval_minutes,
+            last_update: None,
+            last_attempt: None,
+            last_error: None,
+        }
+    }
+
+    /// Whether a scheduled refresh is due at `now`
+    pub fn is_due(&self, now: chrono::DateTime<chrono::Utc>) -> bool {
+        if self.refresh_interval_minutes == 0 {
+            return false;
+        }
+        let interval = chrono::Duration::minutes(self.refresh_interval_minutes.into());
+        self.last_attempt.is_none_or(|last| now - last >= interval)
+    }
+}
+
+/// Nodes decoded from a subscription, along with the number of entries that weren't valid links
+pub struct FetchedNodes {
+    pub nodes: Vec<OverTlsNode>,
+    pub rejected: usize,
+}
+
+/// Result of a fetch running in the background, sent back to the UI thread
+pub struct FetchOutcome {
+    /// The profile the subscription belongs to, another one may be active by the time the fetch ends
+    pub profile: String,
+    pub id: SubscriptionId,
+    pub result: std::io::Result<FetchedNodes>,
+}
+
+/// Fetch a subscription of `profile` on a background thread, the outcome is sent through `tx`
+pub fn spawn_fetch(profile: String, id: SubscriptionId, url: String, tx: std::sync::mpsc::Sender<FetchOutcome>) {
+    std::thread::spawn(move || {
+        let result = fetch_subscription(&url);
+        if tx.send(FetchOutcome { profile, id, result }).is_ok() {
+            fltk::app::awake();
+        }
+    });
+}
+
+/// Download and decode a subscription, blocking
+pub fn fetch_subscription(url: &str) -> std::io::Result<FetchedNodes> {
+    let client = reqwest::blocking::Client::builder()
+        .timeout(FETCH_TIMEOUT)
+        .user_agent(concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION")))
+        .build()
+        .map_err(|e| std::io::Error::other(format!("Failed to create HTTP client: {e}")))?;
+    let body = client
+        .get(url)
+        .send()
+        .and_then(|response| response.error_for_status())
+        .and_then(|response| response.text())
+        .map_err(|e| std::io::Error::other(format!("Failed to fetch subscription: {e}")))?;
+    decode_subscription(&body)
+}
+
+/// Decode a subscription body: base64 of `ssr://` or `overtls://` links one per line, or the links as plain text
+pub fn decode_subscription(body: &str) -> std::io::Result<FetchedNodes> {
+    let text = decode_base64_text(body).unwrap_or_else(|| body.to_string());
+    let mut nodes = Vec::new();
+    let mut rejected = 0;
+    for line in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+        match crate::share_link::parse_share_link(line) {
+            Ok(node) => nodes.push(node),
+            Err(e) => {
+                log::debug!("Skipping invalid subscription entry: {e}");
+                rejected += 1;
+            }
+        }
+    }
+    if nodes.is_empty() && rejected > 0 {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("Subscription has no valid ssr:// or overtls:// links, {rejected} invalid entries"),
+        ));
+    }
+    Ok(FetchedNodes { nodes, rejected })
+}
+
+pub(crate) fn decode_base64_text(body: &str) -> Option<String> {
+    // Providers differ: standard or URL-safe alphabet, with or without padding, sometimes wrapped in lines
+    let compact: String = body.chars().filter(|c| !c.is_whitespace()).collect();
+    let compact = compact.trim_end_matches('=');
+    let bytes = [STANDARD_NO_PAD, URL_SAFE_NO_PAD]
+        .iter()
+        .find_map(|engine| engine.decode(compact).ok())?;
+    String::from_utf8(bytes).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::engine::general_purpose::STANDARD;
+    use std::io::{Read, Write};
+
+    fn sample_links() -> Vec<String> {
+        let node = crate::states_manager::test_fixtures::fixture_node();
+        let mut other = node.clone();
+        other.remarks = Some("Second".into());
+        vec![node.generate_ssr_url().unwrap(), other.generate_ssr_url().unwrap()]
+    }
+
+    /// Serve `body` to a single HTTP request on a local port, returns the URL to fetch
+    fn serve_once(body: String) -> String {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0u8; 1024];
+            while !request.ends_with(b"\r\n\r\n") {
+                let n = stream.read(&mut buf).unwrap();
+                if n == 0 {
+                    break;
+                }
+                request.extend_from_slice(&buf[..n]);
+            }
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            );
+            stream.write_all(response.as_bytes()).unwrap();
+        });
+        format!("http://{addr}/subscription")
+    }
+
+    #[test]
+    fn fetch_subscription_from_local_server() {
+        let links = sample_links();
+        let plain = format!("{}\nnot-a-link\n{}\n", links[0], links[1]);
+        let url = serve_once(STANDARD.encode(plain));
+
+        let fetched = fetch_subscription(&url).unwrap();
+        assert_eq!(fetched.nodes.len(), 2);
+        assert_eq!(fetched.rejected, 1);
+        assert_eq!(fetched.nodes[0].remarks.as_deref(), Some("Example"));
+        assert_eq!(fetched.nodes[1].remarks.as_deref(), Some("Second"));
+    }
+
+    #[test]
+    fn decode_plain_text_subscription() {
+        let links = sample_links();
+        let fetched = decode_subscription(&links.join("\r\n")).unwrap();
+        assert_eq!(fetched.nodes.len(), 2);
+        assert_eq!(fetched.rejected, 0);
+        assert!(decode_subscription("garbage").is_err());
+    }
+
+    #[test]
+    fn subscription_due_after_interval() {
+        let now = chrono::Utc::now();
+        let mut subscription = Subscription::new("test", "http://127.0.0.1/", 30);
+        assert!(subscription.is_due(now));
+        subscription.last_attempt = Some(now - chrono::Duration::minutes(10));
+        assert!(!subscription.is_due(now));
+        assert!(subscription.is_due(now + chrono::Duration::minutes(20)));
+        subscription.refresh_interval_minutes = 0;
+        assert!(!subscription.is_due(now + chrono::Duration::days(1)));
+    }
+}
@@ -64,6 +64,14 @@ pub fn file_chooser_open_file(title: &str, default_path: Option<&str>, filter: &
         .pick_file()
 }
 
+pub fn file_chooser_open_files(title: &str, default_path: Option<&str>, filter: &str, filter_exts: &[&str]) -> Option<Vec<PathBuf>> {
+    rfd::FileDialog::new()
+        .set_title(title)
+        .set_directory(default_path.unwrap_or("."))
+        .add_filter(filter, filter_exts)
+        .pick_files()
+}
+
 pub fn file_chooser_save_file(title: &str, default_path: Option<&str>, filter: &str, filter_exts: &[&str]) -> Option<PathBuf> {
     rfd::FileDialog::new()
         .set_title(title)
@@ -86,3 +94,233 @@ pub const MAIN_ICON_BYTES: &[u8] = include_bytes!("../assets/main.png");
 pub fn get_embedded_main_icon() -> std::io::Result<fltk::image::PngImage> {
     fltk::image::PngImage::from_data(MAIN_ICON_BYTES).map_err(|e| std::io::Error::other(format!("Failed to load embedded icon: {e}")))
 }
+
+// ===============================================================================================
+
+/// The user who started an elevated instance, resolved from the passwd database
+#[cfg(target_os = "linux")]
+#[derive(Debug, Clone)]
+pub struct RealUser {
+    pub name: String,
+    pub uid: u32,
+    pub gid: u32,
+    pub home: PathBuf,
+}
+
+/// The user behind sudo, pkexec or doas when running elevated, `None` otherwise.
+///
+/// Only the environment the elevation tools set is trusted, the home directory always comes from the passwd database.
+#[cfg(target_os = "linux")]
+pub fn real_user() -> Option<&'static RealUser> {
+    static REAL_USER: std::sync::OnceLock<Option<RealUser>> = std::sync::OnceLock::new();
+    REAL_USER
+        .get_or_init(|| {
+            if !nix::unistd::geteuid().is_root() {
+                return None;
+            }
+            let user = lookup_elevating_user();
+            if user.is_none() {
+                log::debug!("Running elevated, but the original user can't be determined");
+            }
+            user
+        })
+        .as_ref()
+}
+
+/// How an elevation tool tells who started it
+#[cfg(target_os = "linux")]
+#[derive(Debug, PartialEq)]
+enum ElevatingUser {
+    Uid(u32),
+    Name(String),
+}
+
+/// The users named by the variables of the elevation tools, in order of precedence:
+/// `SUDO_UID`, `SUDO_USER`, `PKEXEC_UID` then `DOAS_USER`. `env` reads a variable, unset and invalid values are skipped.
+#[cfg(target_os = "linux")]
+fn elevating_user_candidates(env: impl Fn(&str) -> Option<String>) -> Vec<ElevatingUser> {
+    let by_uid = |var: &str| env(var)?.trim().parse::<u32>().ok().map(ElevatingUser::Uid);
+    let by_name = |var: &str| env(var).filter(|name| !name.is_empty()).map(ElevatingUser::Name);
+    [by_uid("SUDO_UID"), by_name("SUDO_USER"), by_uid("PKEXEC_UID"), by_name("DOAS_USER")]
+        .into_iter()
+        .flatten()
+        .collect()
+}
+
+#[cfg(target_os = "linux")]
+fn lookup_elevating_user() -> Option<RealUser> {
+    use nix::unistd::{Uid, User};
+    // The first candidate known to the passwd database
+    let user = elevating_user_candidates(|var| std::env::var(var).ok())
+        .into_iter()
+        .find_map(|candidate| match candidate {
+            ElevatingUser::Uid(uid) => User::from_uid(Uid::from_raw(uid)).ok().flatten(),
+            ElevatingUser::Name(name) => User::from_name(&name).ok().flatten(),
+        })?;
+    Some(RealUser {
+        name: user.name,
+        uid: user.uid.as_raw(),
+        gid: user.gid.as_raw(),
+        home: user.dir,
+    })
+}
+
+/// Hand `path` over to the user behind the elevation, so files we create stay usable by them.
+/// Does nothing when not elevated.
+pub fn give_to_real_user<P: AsRef<std::path::Path>>(path: P, recursive: bool) -> std::io::Result<()> {
+    #[cfg(target_os = "linux")]
+    if let Some(user) = real_user() {
+        chown_path(path.as_ref(), user, recursive)?;
+    }
+    #[cfg(not(target_os = "linux"))]
+    let _ = (path, recursive);
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn chown_path(path: &std::path::Path, user: &RealUser, recursive: bool) -> std::io::Result<()> {
+    // Don't follow symlinks, they may point anywhere
+    std::os::unix::fs::lchown(path, Some(user.uid), Some(user.gid))?;
+    if recursive && std::fs::symlink_metadata(path)?.is_dir() {
+        for entry in std::fs::read_dir(path)? {
+            chown_path(&entry?.path(), user, true)?;
+        }
+    }
+    Ok(())
+}
+
+// ===============================================================================================
+
+/// Register this executable as the handler of `ssr://` and `overtls://` links for the current user,
+/// through a desktop entry, its icon and the shared MIME database
+#[cfg(target_os = "linux")]
+pub fn register_url_handler() -> std::io::Result<PathBuf> {
+    const DESKTOP_FILE: &str = concat!(env!("CARGO_PKG_NAME"), ".desktop");
+    let exe = std::env::current_exe()?;
+    let data_dir = match real_user() {
+        Some(user) => user.home.join(".local/share"),
+        None => dirs::data_dir().ok_or_else(|| std::io::Error::other("Failed to find the data directory"))?,
+    };
+
+    // The embedded icon is 256x256
+    let icons_dir = data_dir.join("icons/hicolor/256x256/apps");
+    std::fs::create_dir_all(&icons_dir)?;
+    let icon_path = icons_dir.join(concat!(env!("CARGO_PKG_NAME"), ".png"));
+    std::fs::write(&icon_path, MAIN_ICON_BYTES)?;
+    give_to_real_user(&icon_path, false)?;
+
+    let apps_dir = data_dir.join("applications");
+    std::fs::create_dir_all(&apps_dir)?;
+    let desktop_path = apps_dir.join(DESKTOP_FILE);
+    std::fs::write(&desktop_path, desktop_entry(&exe))?;
+    give_to_real_user(&desktop_path, false)?;
+
+    for scheme in ["ssr", "overtls"] {
+        let mime = format!("x-scheme-handler/{scheme}");
+        let status = std::process::Command::new("xdg-mime")
+            .args(["default", DESKTOP_FILE, &mime])
+            .status();
+        match status {
+            Ok(status) if status.success() => {}
+            Ok(status) => return Err(std::io::Error::other(format!("xdg-mime failed for {mime}: {status}"))),
+            Err(e) => return Err(std::io::Error::other(format!("Failed to run xdg-mime: {e}"))),
+        }
+    }
+    // Only speeds up the discovery by desktop environments, not every system has it
+    if let Err(e) = std::process::Command::new("update-desktop-database").arg(&apps_dir).status() {
+        log::debug!("update-desktop-database not run: {e}");
+    }
+    Ok(desktop_path)
+}
+
+/// Desktop entry launching `exe` with the clicked links
+#[cfg(target_os = "linux")]
+fn desktop_entry(exe: &std::path::Path) -> String {
+    format!(
+        "[Desktop Entry]
+Type=Application
+Name=OverTLS GUI
+Comment={}
+Exec={} %U
+Icon={}
+Terminal=false
+Categories=Network;Utility;
+MimeType=x-scheme-handler/ssr;x-scheme-handler/overtls;
+",
+        env!("CARGO_PKG_DESCRIPTION"),
+        quote_exec_arg(&exe.to_string_lossy()),
+        env!("CARGO_PKG_NAME"),
+    )
+}
+
+/// Quote an argument of the `Exec` key, which splits on spaces.
+/// Reserved characters get a backslash, itself escaped once more as the value is unescaped as a string first.
+#[cfg(target_os = "linux")]
+fn quote_exec_arg(arg: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in arg.chars() {
+        match c {
+            '\\' => quoted.push_str(r"\\\\"),
+            '"' | '`' | '$' => {
+                quoted.push_str(r"\\");
+                quoted.push(c);
+            }
+            // Field codes start with `%`
+            '%' => quoted.push_str("%%"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// macOS hands clicked links over as Apple Events rather than arguments, which the app doesn't receive
+#[cfg(not(target_os = "linux"))]
+pub fn register_url_handler() -> std::io::Result<PathBuf> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        format!("Registering the link handler isn't supported on {}", host_os_name()),
+    ))
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn elevating_user_resolution_order() {
+        let candidates = |vars: &[(&str, &str)]| {
+            let vars: std::collections::HashMap<String, String> = vars.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+            elevating_user_candidates(|var| vars.get(var).cloned())
+        };
+        use ElevatingUser::{Name, Uid};
+        assert!(candidates(&[]).is_empty());
+        assert_eq!(candidates(&[("SUDO_UID", " 1000 ")]), [Uid(1000)]);
+        assert_eq!(candidates(&[("SUDO_USER", "alice")]), [Name("alice".into())]);
+        assert_eq!(candidates(&[("PKEXEC_UID", "1001")]), [Uid(1001)]);
+        assert_eq!(candidates(&[("DOAS_USER", "bob")]), [Name("bob".into())]);
+        assert_eq!(
+            candidates(&[
+                ("DOAS_USER", "bob"),
+                ("PKEXEC_UID", "1001"),
+                ("SUDO_USER", "alice"),
+                ("SUDO_UID", "1000")
+            ]),
+            [Uid(1000), Name("alice".into()), Uid(1001), Name("bob".into())]
+        );
+        // Invalid values are skipped, not fatal
+        assert_eq!(
+            candidates(&[("SUDO_UID", "alice"), ("SUDO_USER", ""), ("PKEXEC_UID", "-1"), ("DOAS_USER", "bob")]),
+            [Name("bob".into())]
+        );
+    }
+
+    #[test]
+    fn desktop_entry_quotes_the_executable() {
+        let entry = desktop_entry(std::path::Path::new("/opt/My Apps/a\"b\\c%d"));
+        assert!(entry.contains(r#"Exec="/opt/My Apps/a\\"b\\\\c%%d" %U"#), "{entry}");
+        assert!(entry.contains("MimeType=x-scheme-handler/ssr;x-scheme-handler/overtls;\n"));
+        assert!(entry.contains(concat!("Icon=", env!("CARGO_PKG_NAME"), "\n")));
+        assert_eq!(quote_exec_arg("/usr/bin/overtls-gui"), "\"/usr/bin/overtls-gui\"");
+    }
+}
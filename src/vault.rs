@@ -0,0 +1,179 @@
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use chacha20poly1305::{
+    Key, XChaCha20Poly1305, XNonce,
+    aead::{Aead, AeadCore, KeyInit, OsRng, rand_core::RngCore},
+};
+use serde::{Deserialize, Serialize};
+use subtle::ConstantTimeEq;
+use zeroize::Zeroize;
+
+const KDF_NAME: &str = "argon2id";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+
+/// Secret data as stored in config.json, sealed with a key derived from the master passphrase
+#[derive(Clone, Serialize, Deserialize)]
+pub struct EncryptedBlob {
+    pub kdf: String,
+    pub salt: String,
+    pub nonce: String,
+    pub ciphertext: String,
+}
+
+/// Key derived from the master passphrase. It only lives in memory, the passphrase itself is never kept.
+#[derive(Clone)]
+pub struct Vault {
+    salt: [u8; SALT_LEN],
+    key: [u8; 32],
+}
+
+impl std::fmt::Debug for Vault {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Vault").finish_non_exhaustive()
+    }
+}
+
+impl Drop for Vault {
+    fn drop(&mut self) {
+        self.key.zeroize();
+    }
+}
+
+impl Vault {
+    /// Create a vault for a new passphrase, with a fresh random salt
+    pub fn new(passphrase: &str) -> std::io::Result<Self> {
+        let mut salt = [0u8; SALT_LEN];
+        OsRng
+            .try_fill_bytes(&mut salt)
+            .map_err(|e| std::io::Error::other(format!("Failed to generate salt: {e}")))?;
+        let key = derive_key(passphrase, &salt)?;
+        Ok(Vault { salt, key })
+    }
+
+    /// Derive the key for `passphrase` and decrypt `blob` with it.
+    /// A wrong passphrase yields a `PermissionDenied` error.
+    pub fn unlock(passphrase: &str, blob: &EncryptedBlob) -> std::io::Result<(Self, Vec<u8>)> {
+        if blob.kdf != KDF_NAME {
+            return Err(invalid_data(format!("Unsupported key derivation function '{}'", blob.kdf)));
+        }
+        let salt: [u8; SALT_LEN] = decode_base64(&blob.salt)?
+            .try_into()
+            .map_err(|_| invalid_data("Invalid salt length"))?;
+        let vault = Vault {
+            salt,
+            key: derive_key(passphrase, &salt)?,
+        };
+        let plaintext = vault.decrypt(blob)?;
+        Ok((vault, plaintext))
+    }
+
+    /// Check `passphrase` against this vault without decrypting anything
+    pub fn verify(&self, passphrase: &str) -> bool {
+        derive_key(passphrase, &self.salt).is_ok_and(|mut key| {
+            // In constant time, so the comparison leaks nothing about the key
+            let matches = key.ct_eq(&self.key).into();
+            key.zeroize();
+            matches
+        })
+    }
+
+    pub fn encrypt(&self, plaintext: &[u8]) -> std::io::Result<EncryptedBlob> {
+        let cipher = XChaCha20Poly1305::new(Key::from_slice(&self.key));
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = cipher
+            .encrypt(&nonce, plaintext)
+            .map_err(|e| std::io::Error::other(format!("Encryption failed: {e}")))?;
+        Ok(EncryptedBlob {
+            kdf: KDF_NAME.to_string(),
+            salt: BASE64.encode(self.salt),
+            nonce: BASE64.encode(nonce),
+            ciphertext: BASE64.encode(ciphertext),
+        })
+    }
+
+    /// Decrypt a blob sealed by this vault, i.e. with the same passphrase and salt
+    pub fn decrypt(&self, blob: &EncryptedBlob) -> std::io::Result<Vec<u8>> {
+        if decode_base64(&blob.salt)? != self.salt {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::PermissionDenied,
+                "Data was encrypted with another passphrase",
+            ));
+        }
+        let nonce = decode_base64(&blob.nonce)?;
+        if nonce.len() != NONCE_LEN {
+            return Err(invalid_data("Invalid nonce length"));
+        }
+        let ciphertext = decode_base64(&blob.ciphertext)?;
+        let cipher = XChaCha20Poly1305::new(Key::from_slice(&self.key));
+        cipher
+            .decrypt(XNonce::from_slice(&nonce), ciphertext.as_slice())
+            .map_err(|_| std::io::Error::new(std::io::ErrorKind::PermissionDenied, "Wrong passphrase or corrupted data"))
+    }
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> std::io::Result<[u8; 32]> {
+    let mut key = [0u8; 32];
+    argon2::Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| std::io::Error::other(format!("Key derivation failed: {e}")))?;
+    Ok(key)
+}
+
+fn decode_base64(s: &str) -> std::io::Result<Vec<u8>> {
+    BASE64.decode(s).map_err(|e| invalid_data(format!("Invalid base64 data: {e}")))
+}
+
+fn invalid_data<E: Into<Box<dyn std::error::Error + Send + Sync>>>(e: E) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encrypt_and_unlock_round_trip() {
+        let vault = Vault::new("correct horse").unwrap();
+        let blob = vault.encrypt(b"secret nodes").unwrap();
+        assert_eq!(vault.decrypt(&blob).unwrap(), b"secret nodes");
+
+        let (unlocked, plaintext) = Vault::unlock("correct horse", &blob).unwrap();
+        assert_eq!(plaintext, b"secret nodes");
+        assert_eq!(unlocked.decrypt(&vault.encrypt(b"more").unwrap()).unwrap(), b"more");
+    }
+
+    #[test]
+    fn wrong_passphrase_is_rejected() {
+        let vault = Vault::new("correct horse").unwrap();
+        let blob = vault.encrypt(b"secret nodes").unwrap();
+        let err = Vault::unlock("battery staple", &blob).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::PermissionDenied);
+
+        assert!(vault.verify("correct horse"));
+        assert!(!vault.verify("battery staple"));
+    }
+
+    #[test]
+    fn malformed_blobs_are_rejected() {
+        let vault = Vault::new("correct horse").unwrap();
+        let blob = vault.encrypt(b"secret nodes").unwrap();
+
+        let short_salt = EncryptedBlob {
+            salt: BASE64.encode([0u8; SALT_LEN - 1]),
+            ..blob.clone()
+        };
+        assert_eq!(
+            Vault::unlock("correct horse", &short_salt).unwrap_err().kind(),
+            std::io::ErrorKind::InvalidData
+        );
+
+        let mut ciphertext = BASE64.decode(&blob.ciphertext).unwrap();
+        ciphertext[0] ^= 1;
+        let tampered = EncryptedBlob {
+            ciphertext: BASE64.encode(ciphertext),
+            ..blob
+        };
+        assert_eq!(vault.decrypt(&tampered).unwrap_err().kind(), std::io::ErrorKind::PermissionDenied);
+        assert!(Vault::unlock("correct horse", &tampered).is_err());
+    }
+}